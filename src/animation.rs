@@ -0,0 +1,67 @@
+use std::thread::sleep;
+
+use crossterm::{
+    execute,
+    terminal::{Clear, ClearType},
+};
+
+use crate::{
+    board::{display_board, get_board_width},
+    engine::FRAME_DURATION,
+    events::GameEvent,
+    tetromino::EMPTY,
+};
+
+const LOCK_FLASH_FRAMES: usize = 4;
+const LINE_CLEAR_FRAMES: usize = 24;
+const LINE_CLEAR_BLINK_FRAMES: usize = 6;
+const FLASH: char = '@';
+const CLEARING: char = '=';
+
+pub fn play_event_animation(event: &GameEvent, board: &[Vec<char>]) -> std::io::Result<()> {
+    match event {
+        GameEvent::PieceLocked(tetromino_positions) => {
+            let mut flash_board = board.to_vec();
+            for coordinate in tetromino_positions {
+                flash_board[coordinate.get_y() as usize][coordinate.get_x() as usize] = FLASH;
+            }
+            draw_frames(&flash_board, LOCK_FLASH_FRAMES)?;
+        }
+        GameEvent::LinesCleared(rows) => {
+            for blink in 0..LINE_CLEAR_FRAMES / LINE_CLEAR_BLINK_FRAMES {
+                let fill = if blink.is_multiple_of(2) {
+                    CLEARING
+                } else {
+                    EMPTY
+                };
+                let mut clear_board = board.to_vec();
+                for row in rows {
+                    for cell in clear_board[*row]
+                        .iter_mut()
+                        .take(get_board_width(board) - 1)
+                        .skip(1)
+                    {
+                        *cell = fill;
+                    }
+                }
+                draw_frames(&clear_board, LINE_CLEAR_BLINK_FRAMES)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+pub fn has_animation(event: &GameEvent) -> bool {
+    matches!(
+        event,
+        GameEvent::PieceLocked(_) | GameEvent::LinesCleared(_)
+    )
+}
+
+fn draw_frames(board: &[Vec<char>], frames: usize) -> std::io::Result<()> {
+    execute!(std::io::stdout(), Clear(ClearType::FromCursorUp))?;
+    display_board(board);
+    sleep(FRAME_DURATION * frames as u32);
+    Ok(())
+}
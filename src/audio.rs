@@ -1,17 +1,46 @@
-use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+use rodio::cpal::{
+    self, default_host,
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, SampleFormat, SizedSample,
+};
+use rodio::dynamic_mixer::{mixer, DynamicMixer, DynamicMixerController};
+use rodio::{Decoder, Sink, Source, StreamError};
+use std::cell::{Cell, RefCell};
 use std::error::Error;
 use std::fmt;
 use std::fs::File;
 use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
 
-const FILEPATH: &str = "tetris_theme.mp3";
+use crate::{
+    audio_backend::AudioBackend,
+    events::GameEvent,
+    paths::get_data_directory,
+    sfx::{create_effect_source, get_sound_effect, SoundEffect},
+    synth::Tempo,
+    theme::create_theme_source,
+};
+
+const MUSIC_FILENAME: &str = "tetris_theme.mp3";
+const OUTPUT_CHECK_INTERVAL: Duration = Duration::from_secs(3);
+
+pub type MusicSource = Box<dyn Source<Item = f32> + Send>;
+
+#[cfg(feature = "embedded-music")]
+const EMBEDDED_MUSIC: &[u8] = include_bytes!("../tetris_theme.mp3");
 
 #[derive(Debug)]
 pub enum AudioError {
     Decoder(rodio::decoder::DecoderError),
     Stream(rodio::StreamError),
-    Play(rodio::PlayError),
     Io(std::io::Error),
+    NoDevice,
+    DeviceLost(String),
+    // Reported by the output stream while it was playing
+    StreamFailed(String),
 }
 
 impl fmt::Display for AudioError {
@@ -19,8 +48,10 @@ impl fmt::Display for AudioError {
         match self {
             AudioError::Decoder(error) => write!(f, "Decoder error: {}", error),
             AudioError::Stream(error) => write!(f, "Stream error: {}", error),
-            AudioError::Play(error) => write!(f, "Play error: {}", error),
             AudioError::Io(error) => write!(f, "Failed to open file: {}", error),
+            AudioError::NoDevice => write!(f, "No audio output device found"),
+            AudioError::DeviceLost(name) => write!(f, "Audio device {} was disconnected", name),
+            AudioError::StreamFailed(message) => write!(f, "Audio output failed: {}", message),
         }
     }
 }
@@ -30,8 +61,8 @@ impl Error for AudioError {
         match self {
             AudioError::Decoder(error) => Some(error),
             AudioError::Stream(error) => Some(error),
-            AudioError::Play(error) => Some(error),
             AudioError::Io(error) => Some(error),
+            AudioError::NoDevice | AudioError::DeviceLost(_) | AudioError::StreamFailed(_) => None,
         }
     }
 }
@@ -48,50 +79,383 @@ impl From<rodio::StreamError> for AudioError {
     }
 }
 
-impl From<rodio::PlayError> for AudioError {
-    fn from(error: rodio::PlayError) -> Self {
-        AudioError::Play(error)
-    }
-}
-
 impl From<std::io::Error> for AudioError {
     fn from(error: std::io::Error) -> Self {
         AudioError::Io(error)
     }
 }
 
-#[allow(dead_code)]
-pub struct Audio {
-    stream: OutputStream,
-    output_stream_handle: OutputStreamHandle,
+// A track in the playlist that can be selected in the options
+#[derive(Debug, Clone, PartialEq)]
+pub enum Track {
+    File(PathBuf),
+    #[cfg(feature = "embedded-music")]
+    Embedded,
+    Synthesized,
+}
+
+impl Track {
+    pub fn get_name(&self) -> String {
+        match self {
+            Track::File(path) => match path.file_stem() {
+                Some(stem) => stem.to_string_lossy().into_owned(),
+                None => path.display().to_string(),
+            },
+            #[cfg(feature = "embedded-music")]
+            Track::Embedded => String::from("Embedded theme"),
+            Track::Synthesized => String::from("Synthesized theme"),
+        }
+    }
+}
+
+// The stream is built here instead of by rodio, which prints stream errors over the game
+struct AudioOutput {
+    // Playback stops when the stream is dropped
+    #[allow(dead_code)]
+    stream: cpal::Stream,
+    mixer: Arc<DynamicMixerController<f32>>,
     sink: Sink,
+    device_name: String,
+    // Set by the stream's error callback, on the audio thread
+    stream_error: Arc<Mutex<Option<String>>>,
+}
+
+// The default device as last seen by the probe thread, taken when it is checked.
+// The inner option is None when there is no device
+type ProbedDevice = Arc<Mutex<Option<Option<String>>>>;
+
+// Keeps working without an output, which is opened again when a device shows up.
+// Everything needed to restore the music is kept here, not in the sink
+pub struct Audio {
+    output: RefCell<Option<AudioOutput>>,
+    error: RefCell<Option<AudioError>>,
+    probed_device: ProbedDevice,
+    track: RefCell<Track>,
+    music_playing: Cell<bool>,
+    // Shared by the menus and the game, which only get a reference to the audio
+    music_volume: Cell<f32>,
+    effects_volume: Cell<f32>,
+    muted: Cell<bool>,
+    tempo: Tempo,
+    // The synthesized theme changes its tempo, other tracks are sped up which raises the pitch
+    synthesized: Cell<bool>,
 }
 
 impl Audio {
-    pub fn get_sink(&self) -> &Sink {
-        &self.sink
+    fn open_output(&self) {
+        match create_output() {
+            Ok(output) => {
+                *self.output.borrow_mut() = Some(output);
+                *self.error.borrow_mut() = None;
+                if let Err(error) = self.load_track() {
+                    *self.error.borrow_mut() = Some(error);
+                }
+                self.apply_music_volume();
+                self.set_tempo(self.tempo.get());
+                self.set_music_playing(self.music_playing.get());
+            }
+            Err(error) => {
+                *self.output.borrow_mut() = None;
+                *self.error.borrow_mut() = Some(error);
+            }
+        }
+    }
+
+    // Replaces the music in the sink, which is left paused
+    fn load_track(&self) -> Result<(), AudioError> {
+        let output = self.output.borrow();
+        let Some(output) = output.as_ref() else {
+            return Ok(());
+        };
+        output.sink.clear();
+        output
+            .sink
+            .append(create_music_source(&self.track.borrow(), &self.tempo)?);
+        Ok(())
+    }
+
+    // Effects go straight to the output stream, so they mix with the music
+    // and are not affected by its speed or pausing
+    fn play_effect(&self, effect: SoundEffect) {
+        if self.muted.get() {
+            return;
+        }
+        if let Some(output) = &*self.output.borrow() {
+            output
+                .mixer
+                .add(create_effect_source(effect).amplify(self.effects_volume.get()));
+        }
+    }
+
+    fn apply_music_volume(&self) {
+        if let Some(output) = &*self.output.borrow() {
+            if self.muted.get() {
+                output.sink.set_volume(0.0);
+            } else {
+                output.sink.set_volume(self.music_volume.get());
+            }
+        }
+    }
+}
+
+impl AudioBackend for Audio {
+    fn play_event(&self, event: &GameEvent) {
+        if let Some(effect) = get_sound_effect(event) {
+            self.play_effect(effect);
+        }
+    }
+
+    fn play_track(&self, track: &Track) -> Result<(), AudioError> {
+        *self.track.borrow_mut() = track.clone();
+        self.synthesized.set(*track == Track::Synthesized);
+        self.load_track()?;
+        self.set_tempo(self.tempo.get());
+        Ok(())
+    }
+
+    fn set_music_playing(&self, playing: bool) {
+        self.music_playing.set(playing);
+        if let Some(output) = &*self.output.borrow() {
+            if playing {
+                output.sink.play();
+            } else {
+                output.sink.pause();
+            }
+        }
+    }
+
+    fn set_volumes(&self, music_volume: u8, effects_volume: u8) {
+        self.music_volume.set(music_volume as f32 / 100.0);
+        self.effects_volume.set(effects_volume as f32 / 100.0);
+        self.apply_music_volume();
+    }
+
+    fn set_tempo(&self, tempo: f32) {
+        self.tempo.set(tempo);
+        if let Some(output) = &*self.output.borrow() {
+            if self.synthesized.get() {
+                output.sink.set_speed(1.0);
+            } else {
+                output.sink.set_speed(tempo);
+            }
+        }
     }
+
+    fn toggle_mute(&self) {
+        self.muted.set(!self.muted.get());
+        self.apply_music_volume();
+    }
+
+    fn is_muted(&self) -> bool {
+        self.muted.get()
+    }
+
+    fn is_enabled(&self) -> bool {
+        true
+    }
+
+    fn is_available(&self) -> bool {
+        self.output.borrow().is_some()
+    }
+
+    fn get_error(&self) -> Option<String> {
+        self.error.borrow().as_ref().map(AudioError::to_string)
+    }
+
+    // A failed stream is closed, and reopened once the probe thread sees a device.
+    // A device going away is not always an error, so the default device is compared
+    // with the one that is playing. Nothing here waits for the audio system
+    fn check_output(&self) {
+        let stream_error = match &*self.output.borrow() {
+            Some(output) => output.stream_error.lock().unwrap().take(),
+            None => None,
+        };
+        if let Some(message) = stream_error {
+            self.output.borrow_mut().take();
+            *self.error.borrow_mut() = Some(AudioError::StreamFailed(message));
+            return;
+        }
+        let Some(default_device_name) = self.probed_device.lock().unwrap().take() else {
+            return;
+        };
+        let lost_device_name = match &*self.output.borrow() {
+            Some(output) if default_device_name.as_ref() == Some(&output.device_name) => return,
+            Some(output) => Some(output.device_name.clone()),
+            None if default_device_name.is_none() => return,
+            None => None,
+        };
+        self.output.borrow_mut().take();
+        self.open_output();
+        if let (false, Some(device_name)) = (self.is_available(), lost_device_name) {
+            *self.error.borrow_mut() = Some(AudioError::DeviceLost(device_name));
+        }
+    }
+}
+
+// Loops forever, the synthesized theme follows the tempo
+pub fn create_music_source(track: &Track, tempo: &Tempo) -> Result<MusicSource, AudioError> {
+    match track {
+        Track::File(path) => {
+            let buffer_reader = create_buffer_reader(path)?;
+            Ok(Box::new(
+                Decoder::new(buffer_reader)?
+                    .repeat_infinite()
+                    .convert_samples(),
+            ))
+        }
+        #[cfg(feature = "embedded-music")]
+        Track::Embedded => {
+            let embedded_reader = std::io::Cursor::new(EMBEDDED_MUSIC);
+            Ok(Box::new(
+                Decoder::new(embedded_reader)?
+                    .repeat_infinite()
+                    .convert_samples(),
+            ))
+        }
+        Track::Synthesized => Ok(Box::new(create_theme_source(tempo))),
+    }
+}
+
+// Audio starts without an output when no device can be opened, and keeps trying
+pub fn play_audio(track: &Track) -> Audio {
+    let audio = Audio {
+        output: RefCell::new(None),
+        error: RefCell::new(None),
+        probed_device: Arc::new(Mutex::new(None)),
+        track: RefCell::new(track.clone()),
+        music_playing: Cell::new(true),
+        music_volume: Cell::new(1.0),
+        effects_volume: Cell::new(1.0),
+        muted: Cell::new(false),
+        tempo: Tempo::new(1.0),
+        synthesized: Cell::new(*track == Track::Synthesized),
+    };
+    audio.open_output();
+    start_device_probe(Arc::downgrade(&audio.probed_device));
+    audio
 }
 
-pub fn play_audio() -> Result<Audio, AudioError> {
-    let (stream, output_stream_handle) = OutputStream::try_default()?;
-    let buffer_reader = create_buffer_reader()?;
-    let source = Decoder::new(buffer_reader)?;
-    let sink = Sink::try_new(&output_stream_handle)?;
-    sink.append(source.repeat_infinite());
-    sink.play();
-    Ok(Audio {
+// Looking up devices can take long enough to stall the game, so it runs on its own thread,
+// which stops once the audio is dropped
+fn start_device_probe(probed_device: Weak<Mutex<Option<Option<String>>>>) {
+    thread::spawn(move || loop {
+        thread::sleep(OUTPUT_CHECK_INTERVAL);
+        let default_device_name = get_default_device_name();
+        match probed_device.upgrade() {
+            Some(probed_device) => *probed_device.lock().unwrap() = Some(default_device_name),
+            None => break,
+        }
+    });
+}
+
+fn create_output() -> Result<AudioOutput, AudioError> {
+    let device = default_host()
+        .default_output_device()
+        .ok_or(AudioError::NoDevice)?;
+    let device_name = device.name().unwrap_or_default();
+    let config = device
+        .default_output_config()
+        .map_err(StreamError::DefaultStreamConfigError)?;
+    let (mixer_controller, mixer_output) = mixer::<f32>(config.channels(), config.sample_rate().0);
+    let stream_error = Arc::new(Mutex::new(None));
+    let stream = match config.sample_format() {
+        SampleFormat::F32 => build_stream::<f32>(&device, &config, mixer_output, &stream_error),
+        SampleFormat::I16 => build_stream::<i16>(&device, &config, mixer_output, &stream_error),
+        SampleFormat::U16 => build_stream::<u16>(&device, &config, mixer_output, &stream_error),
+        SampleFormat::I32 => build_stream::<i32>(&device, &config, mixer_output, &stream_error),
+        _ => Err(cpal::BuildStreamError::StreamConfigNotSupported),
+    }
+    .map_err(StreamError::BuildStreamError)?;
+    stream.play().map_err(StreamError::PlayStreamError)?;
+
+    // The music gets its own sink, so it can be paused and sped up without the effects
+    let (sink, music_output) = Sink::new_idle();
+    mixer_controller.add(music_output);
+    Ok(AudioOutput {
         stream,
-        output_stream_handle,
+        mixer: mixer_controller,
         sink,
+        device_name,
+        stream_error,
     })
 }
 
-fn create_buffer_reader() -> std::io::Result<BufReader<File>> {
-    match File::open(FILEPATH) {
+fn build_stream<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::SupportedStreamConfig,
+    mut mixer_output: DynamicMixer<f32>,
+    stream_error: &Arc<Mutex<Option<String>>>,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    let stream_error = Arc::clone(stream_error);
+    device.build_output_stream(
+        &config.config(),
+        move |data: &mut [T], _| {
+            for sample in data.iter_mut() {
+                *sample = T::from_sample(mixer_output.next().unwrap_or(0.0));
+            }
+        },
+        move |error| *stream_error.lock().unwrap() = Some(error.to_string()),
+        None,
+    )
+}
+
+fn get_default_device_name() -> Option<String> {
+    default_host()
+        .default_output_device()
+        .map(|device| device.name().unwrap_or_default())
+}
+
+// The music file that was found comes first, so it plays unless another track is selected.
+// Without one the embedded track is next, if the binary was built with one
+pub fn get_playlist(music_file: Option<&Path>, playlist_files: &[PathBuf]) -> Vec<Track> {
+    let mut playlist: Vec<Track> = Vec::new();
+    if let Some(path) = find_music_file(music_file) {
+        playlist.push(Track::File(path));
+    }
+    for path in playlist_files {
+        let track = Track::File(path.clone());
+        if path.is_file() && !playlist.contains(&track) {
+            playlist.push(track);
+        }
+    }
+    #[cfg(feature = "embedded-music")]
+    playlist.push(Track::Embedded);
+    playlist.push(Track::Synthesized);
+    playlist
+}
+
+// Falls back to the first track when the selected one is gone
+pub fn get_selected_track(playlist: &[Track], track_name: Option<&str>) -> usize {
+    playlist
+        .iter()
+        .position(|track| Some(track.get_name().as_str()) == track_name)
+        .unwrap_or(0)
+}
+
+fn create_buffer_reader(music_path: &Path) -> std::io::Result<BufReader<File>> {
+    match File::open(music_path) {
         Ok(file) => {
             return Ok(BufReader::new(file));
         }
         Err(error) => Err(error),
     }
 }
+
+// Looks for the music in the configured path first, then in the data directory
+// and finally next to the executable
+pub fn find_music_file(configured_path: Option<&Path>) -> Option<PathBuf> {
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    if let Some(path) = configured_path {
+        candidates.push(path.to_path_buf());
+    }
+    if let Some(directory) = get_data_directory() {
+        candidates.push(directory.join(MUSIC_FILENAME));
+    }
+    if let Some(directory) = std::env::current_exe()
+        .ok()
+        .and_then(|path| path.parent().map(Path::to_path_buf))
+    {
+        candidates.push(directory.join(MUSIC_FILENAME));
+    }
+    candidates.into_iter().find(|path| path.is_file())
+}
@@ -0,0 +1,208 @@
+#[cfg(test)]
+use std::cell::{Cell, RefCell};
+
+#[cfg(test)]
+use crate::sfx::{get_sound_effect, SoundEffect};
+use crate::{
+    audio::{AudioError, Track},
+    events::GameEvent,
+};
+
+pub trait AudioBackend {
+    // Plays the sound effect of an engine event, if it has one
+    fn play_event(&self, event: &GameEvent);
+    // Replaces the music, which stays paused until it is set to play
+    fn play_track(&self, track: &Track) -> Result<(), AudioError>;
+    fn set_music_playing(&self, playing: bool);
+    // Volumes are in percent
+    fn set_volumes(&self, music_volume: u8, effects_volume: u8);
+    fn set_tempo(&self, tempo: f32);
+    fn toggle_mute(&self);
+    fn is_muted(&self) -> bool;
+    // False when audio was turned off on purpose, which is not reported as a problem
+    fn is_enabled(&self) -> bool;
+    // False when nothing can be heard, whatever is played
+    fn is_available(&self) -> bool;
+    // Why the audio is unavailable or the music is not playing
+    fn get_error(&self) -> Option<String>;
+    // Called regularly, so a lost output device can be replaced
+    fn check_output(&self);
+}
+
+// Used when audio is turned off
+pub struct NullAudio;
+
+impl AudioBackend for NullAudio {
+    fn play_event(&self, _event: &GameEvent) {}
+
+    fn play_track(&self, _track: &Track) -> Result<(), AudioError> {
+        Ok(())
+    }
+
+    fn set_music_playing(&self, _playing: bool) {}
+
+    fn set_volumes(&self, _music_volume: u8, _effects_volume: u8) {}
+
+    fn set_tempo(&self, _tempo: f32) {}
+
+    fn toggle_mute(&self) {}
+
+    fn is_muted(&self) -> bool {
+        false
+    }
+
+    fn is_enabled(&self) -> bool {
+        false
+    }
+
+    fn is_available(&self) -> bool {
+        false
+    }
+
+    fn get_error(&self) -> Option<String> {
+        None
+    }
+
+    fn check_output(&self) {}
+}
+
+// Keeps the sound effects that would have been played, so a headless game
+// can be checked for the sounds it triggered
+#[cfg(test)]
+#[derive(Default)]
+pub struct RecordingAudio {
+    effects: RefCell<Vec<SoundEffect>>,
+    muted: Cell<bool>,
+}
+
+#[cfg(test)]
+impl RecordingAudio {
+    pub fn new() -> RecordingAudio {
+        RecordingAudio::default()
+    }
+
+    pub fn take_effects(&self) -> Vec<SoundEffect> {
+        self.effects.take()
+    }
+}
+
+#[cfg(test)]
+impl AudioBackend for RecordingAudio {
+    fn play_event(&self, event: &GameEvent) {
+        if self.muted.get() {
+            return;
+        }
+        if let Some(effect) = get_sound_effect(event) {
+            self.effects.borrow_mut().push(effect);
+        }
+    }
+
+    fn play_track(&self, _track: &Track) -> Result<(), AudioError> {
+        Ok(())
+    }
+
+    fn set_music_playing(&self, _playing: bool) {}
+
+    fn set_volumes(&self, _music_volume: u8, _effects_volume: u8) {}
+
+    fn set_tempo(&self, _tempo: f32) {}
+
+    fn toggle_mute(&self) {
+        self.muted.set(!self.muted.get());
+    }
+
+    fn is_muted(&self) -> bool {
+        self.muted.get()
+    }
+
+    fn is_enabled(&self) -> bool {
+        true
+    }
+
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn get_error(&self) -> Option<String> {
+        None
+    }
+
+    fn check_output(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        engine::{Engine, Ruleset},
+        input::{InputCommand, KeyAction},
+        mode::GameMode,
+    };
+
+    const INPUTS: [InputCommand; 3] = [
+        InputCommand::Left,
+        InputCommand::RotateCw,
+        InputCommand::HardDrop,
+    ];
+
+    fn play_inputs(engine: &mut Engine, audio: &RecordingAudio) {
+        for command in INPUTS {
+            engine.handle_input(command, KeyAction::Press);
+            for event in engine.take_events() {
+                audio.play_event(&event);
+            }
+        }
+    }
+
+    // The bottom row is filled everywhere except where the first piece lands,
+    // so dropping it clears the row
+    fn create_engine() -> Engine {
+        let ruleset = Ruleset::new(1, GameMode::Marathon);
+        let mut state = Engine::new(&ruleset).get_state();
+        let mut probe = Engine::from_state(&ruleset, state.clone()).unwrap();
+        play_inputs(&mut probe, &RecordingAudio::new());
+        let bottom_row = state.board.len() - 2;
+        let landed_row: Vec<char> = probe.get_board()[bottom_row].clone();
+        let last_column = landed_row.len() - 1;
+        state.board[bottom_row] = landed_row
+            .iter()
+            .enumerate()
+            .map(|(column, cell)| {
+                if column == 0 || column == last_column || *cell == ' ' {
+                    '#'
+                } else {
+                    ' '
+                }
+            })
+            .collect();
+        Engine::from_state(&ruleset, state).unwrap()
+    }
+
+    #[test]
+    fn engine_events_trigger_effects() {
+        let mut engine = create_engine();
+        let audio = RecordingAudio::new();
+        play_inputs(&mut engine, &audio);
+        assert_eq!(
+            audio.take_effects(),
+            [
+                SoundEffect::Move,
+                SoundEffect::Rotate,
+                SoundEffect::HardDrop,
+                SoundEffect::Lock,
+                SoundEffect::LineClear(1),
+            ]
+        );
+        assert!(audio.take_effects().is_empty());
+    }
+
+    #[test]
+    fn muted_effects_are_not_played() {
+        let mut engine = create_engine();
+        let audio = RecordingAudio::new();
+        audio.toggle_mute();
+        play_inputs(&mut engine, &audio);
+        assert!(audio.is_muted());
+        assert!(audio.take_effects().is_empty());
+    }
+}
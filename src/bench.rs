@@ -0,0 +1,62 @@
+use std::time::Instant;
+
+use crate::{
+    engine::{Engine, Ruleset, FRAMES_PER_SECOND},
+    input::{InputCommand, KeyAction},
+};
+
+// Every benchmark game presses these in a loop, one every few frames
+const BENCH_INPUTS: [InputCommand; 8] = [
+    InputCommand::Left,
+    InputCommand::RotateCw,
+    InputCommand::Left,
+    InputCommand::Down,
+    InputCommand::Right,
+    InputCommand::Rotate180,
+    InputCommand::Right,
+    InputCommand::Down,
+];
+const BENCH_INPUT_INTERVAL: u64 = 4;
+// Games that survive an hour of game time are cut off
+const MAX_BENCH_FRAMES: u64 = 60 * 60 * FRAMES_PER_SECOND;
+
+pub fn run_bench(ruleset: &Ruleset, games: usize) {
+    let mut frames = 0;
+    let mut pieces = 0;
+    let mut lines = 0;
+    let start_time = Instant::now();
+    for game in 0..games {
+        let game_ruleset = Ruleset {
+            seed: ruleset.seed.wrapping_add(game as u64),
+            ..ruleset.clone()
+        };
+        let mut engine = Engine::new(&game_ruleset);
+        let mut next_input = 0;
+        while engine.get_game_end().is_none() && engine.get_frame() < MAX_BENCH_FRAMES {
+            if engine.get_frame().is_multiple_of(BENCH_INPUT_INTERVAL) {
+                let command = BENCH_INPUTS[next_input % BENCH_INPUTS.len()];
+                engine.handle_input(command, KeyAction::Press);
+                next_input += 1;
+            }
+            engine.tick();
+            engine.take_events();
+        }
+        frames += engine.get_frame();
+        pieces += engine.get_stats().pieces;
+        lines += engine.get_stats().lines;
+    }
+    let elapsed = start_time.elapsed();
+
+    println!(
+        "Simulated {} games: {} frames, {} pieces, {} lines",
+        games, frames, pieces, lines
+    );
+    let seconds = elapsed.as_secs_f64();
+    if seconds > 0.0 {
+        println!(
+            "Took {:.1} ms, {:.0} frames per second",
+            seconds * 1000.0,
+            frames as f64 / seconds
+        );
+    }
+}
@@ -1,19 +1,41 @@
+use serde::{Deserialize, Serialize};
+
 use crate::tetromino::TETROMINO_SIZE;
 
 const EMPTY: char = ' ';
 const BLOCK: char = '#';
 
-pub const BOARD_WIDTH: usize = 12;
-pub const BOARD_HEIGHT: usize = 22;
+// Sizes count the playable cells, the board adds a wall on both sides and a floor
+pub const DEFAULT_BOARD_SIZE: BoardSize = BoardSize {
+    width: 10,
+    height: 21,
+};
+pub const MIN_BOARD_WIDTH: usize = 6;
+pub const MAX_BOARD_WIDTH: usize = 30;
+pub const MIN_BOARD_HEIGHT: usize = 8;
+pub const MAX_BOARD_HEIGHT: usize = 40;
+
+// Offsets tried in order when a rotated tetromino does not fit where it is
+const KICK_OFFSETS: [(isize, isize); 6] = [(0, 0), (-1, 0), (1, 0), (0, -1), (-2, 0), (2, 0)];
+
+pub type Board = Vec<Vec<char>>;
 
-pub fn init_board() -> [[char; BOARD_WIDTH]; BOARD_HEIGHT] {
-    let mut board = [[EMPTY; BOARD_WIDTH]; BOARD_HEIGHT];
-    for i in 0..BOARD_HEIGHT {
-        for j in 0..BOARD_WIDTH {
-            if i == BOARD_HEIGHT - 1 {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoardSize {
+    pub width: usize,
+    pub height: usize,
+}
+
+pub fn init_board(size: &BoardSize) -> Board {
+    let board_width = size.width + 2;
+    let board_height = size.height + 1;
+    let mut board = vec![vec![EMPTY; board_width]; board_height];
+    for i in 0..board_height {
+        for j in 0..board_width {
+            if i == board_height - 1 {
                 board[i][j] = BLOCK;
             }
-            if j == 0 || j == BOARD_WIDTH - 1 {
+            if j == 0 || j == board_width - 1 {
                 board[i][j] = BLOCK;
             }
         }
@@ -21,7 +43,11 @@ pub fn init_board() -> [[char; BOARD_WIDTH]; BOARD_HEIGHT] {
     board
 }
 
-#[derive(Debug, Clone, Copy)]
+pub fn get_board_width(board: &[Vec<char>]) -> usize {
+    board[0].len()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
 pub struct Coordinate {
     y: isize,
     x: isize,
@@ -46,7 +72,7 @@ pub enum MoveDirection {
 }
 
 pub fn init_tetromino_to_board(
-    board: &mut [[char; BOARD_WIDTH]; BOARD_HEIGHT],
+    board: &mut [Vec<char>],
     tetromino: &[[char; TETROMINO_SIZE]; TETROMINO_SIZE],
     start_x: isize,
     start_y: isize,
@@ -60,7 +86,10 @@ pub fn init_tetromino_to_board(
     let mut index = 0;
     for i in 0..TETROMINO_SIZE {
         for j in 0..TETROMINO_SIZE {
-            if tetromino[i][j] == BLOCK && start_y >= 0 && start_x < (BOARD_WIDTH - 2) as isize {
+            if tetromino[i][j] == BLOCK
+                && start_y >= 0
+                && start_x < (get_board_width(board) - 2) as isize
+            {
                 board[i + start_y as usize][j + start_x as usize] = tetromino[i][j];
                 tetromino_positions[index].x = j as isize + start_x;
                 tetromino_positions[index].y = i as isize + start_y;
@@ -71,10 +100,20 @@ pub fn init_tetromino_to_board(
     tetromino_positions
 }
 
-pub fn display_board(board: &[[char; BOARD_WIDTH]; BOARD_HEIGHT]) {
-    for i in 0..BOARD_HEIGHT {
-        for j in 0..BOARD_WIDTH {
-            print!("{} ", board[i][j]);
+// FNV-1a over every cell, used to compare final boards between runs
+pub fn get_board_hash(board: &[Vec<char>]) -> u64 {
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    for cell in board.iter().flatten() {
+        hash ^= *cell as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01B3);
+    }
+    hash
+}
+
+pub fn display_board(board: &[Vec<char>]) {
+    for row in board {
+        for cell in row {
+            print!("{} ", cell);
         }
         print!("\r\n");
     }
@@ -82,7 +121,7 @@ pub fn display_board(board: &[[char; BOARD_WIDTH]; BOARD_HEIGHT]) {
 
 pub fn drop_tetromino(
     tetromino_positions: &mut [Coordinate; TETROMINO_SIZE],
-    board: &[[char; BOARD_WIDTH]; BOARD_HEIGHT],
+    board: &[Vec<char>],
 ) -> bool {
     if is_allowed_to_drop_tetromino(tetromino_positions, board) {
         for i in 0..TETROMINO_SIZE {
@@ -95,7 +134,7 @@ pub fn drop_tetromino(
 
 pub fn clear_previous_tetromino_from_board(
     tetromino_positions: &[Coordinate; TETROMINO_SIZE],
-    board: &mut [[char; BOARD_WIDTH]; BOARD_HEIGHT],
+    board: &mut [Vec<char>],
 ) {
     for i in 0..TETROMINO_SIZE {
         board[tetromino_positions[i].y as usize][tetromino_positions[i].x as usize] = EMPTY;
@@ -120,7 +159,7 @@ pub fn copy_tetromino_positions(
 
 pub fn write_tetromino_to_board(
     tetromino_positions: &[Coordinate; TETROMINO_SIZE],
-    board: &mut [[char; BOARD_WIDTH]; BOARD_HEIGHT],
+    board: &mut [Vec<char>],
 ) {
     for i in 0..TETROMINO_SIZE {
         board[tetromino_positions[i].y as usize][tetromino_positions[i].x as usize] = BLOCK;
@@ -129,7 +168,7 @@ pub fn write_tetromino_to_board(
 
 pub fn move_tetromino_sideways(
     tetromino_positions: &mut [Coordinate; TETROMINO_SIZE],
-    board: &[[char; BOARD_WIDTH]; BOARD_HEIGHT],
+    board: &[Vec<char>],
     direction: &MoveDirection,
 ) -> bool {
     let direction_is_empty = false;
@@ -193,10 +232,11 @@ impl OutOfBounds {
 
 pub fn is_tetromino_positions_out_of_bounds(
     tetromino_positions: &[Coordinate; TETROMINO_SIZE],
+    board: &[Vec<char>],
 ) -> OutOfBounds {
-    let max_x: isize = (BOARD_WIDTH - 1) as isize;
+    let max_x: isize = (get_board_width(board) - 1) as isize;
     let min_x: isize = 0;
-    let max_y: isize = (BOARD_HEIGHT - 1) as isize;
+    let max_y: isize = (board.len() - 1) as isize;
     let min_y: isize = 0;
     let mut out_of_bounds = OutOfBounds {
         difference: 0,
@@ -251,7 +291,7 @@ pub fn move_tetromino_back_in_bounds(
 
 pub fn is_tetromino_position_overlapping_with_block(
     tetromino_positions: &[Coordinate; TETROMINO_SIZE],
-    board: &[[char; BOARD_WIDTH]; BOARD_HEIGHT],
+    board: &[Vec<char>],
 ) -> bool {
     for i in 0..TETROMINO_SIZE {
         if board[tetromino_positions[i].y as usize][tetromino_positions[i].x as usize] == BLOCK {
@@ -261,25 +301,86 @@ pub fn is_tetromino_position_overlapping_with_block(
     false
 }
 
-pub fn is_full_row(board: &[[char; BOARD_WIDTH]; BOARD_HEIGHT]) -> usize {
-    let mut column_with_full_row = 0;
-    for i in 1..BOARD_HEIGHT - 1 {
-        let mut block_counter = 0;
-        for j in 1..BOARD_WIDTH - 1 {
-            if board[i][j] == BLOCK {
-                block_counter += 1;
-            }
+pub fn kick_tetromino(
+    tetromino_positions: &[Coordinate; TETROMINO_SIZE],
+    board: &[Vec<char>],
+) -> Option<[Coordinate; TETROMINO_SIZE]> {
+    for (x_offset, y_offset) in KICK_OFFSETS {
+        let mut kicked_tetromino_positions = copy_tetromino_positions(tetromino_positions);
+        for coordinate in kicked_tetromino_positions.iter_mut() {
+            coordinate.x += x_offset;
+            coordinate.y += y_offset;
         }
-        if block_counter == 10 {
-            column_with_full_row = i;
+        if is_tetromino_positions_out_of_bounds(&kicked_tetromino_positions, board).get_difference()
+            == 0
+            && !is_tetromino_position_overlapping_with_block(&kicked_tetromino_positions, board)
+        {
+            return Some(kicked_tetromino_positions);
         }
     }
-    column_with_full_row
+    None
+}
+
+// Counts the filled cells diagonal to the center of a T tetromino, the walls count as filled
+pub fn get_t_corner_count(
+    tetromino_positions: &[Coordinate; TETROMINO_SIZE],
+    board: &[Vec<char>],
+) -> usize {
+    // The center is the only block next to all three others
+    let center = tetromino_positions.iter().find(|coordinate| {
+        tetromino_positions
+            .iter()
+            .filter(|other| (other.x - coordinate.x).abs() + (other.y - coordinate.y).abs() == 1)
+            .count()
+            == 3
+    });
+    let center = match center {
+        Some(center) => center,
+        None => return 0,
+    };
+    [(-1, -1), (1, -1), (-1, 1), (1, 1)]
+        .iter()
+        .filter(|(x_offset, y_offset)| {
+            let x = center.x + x_offset;
+            let y = center.y + y_offset;
+            if x < 0 || y < 0 || y as usize >= board.len() || x as usize >= get_board_width(board) {
+                return true;
+            }
+            board[y as usize][x as usize] != EMPTY
+        })
+        .count()
+}
+
+pub fn get_full_rows(board: &[Vec<char>]) -> Vec<usize> {
+    let board_width = get_board_width(board);
+    (1..board.len() - 1)
+        .filter(|row| {
+            board[*row][1..board_width - 1]
+                .iter()
+                .all(|cell| *cell == BLOCK)
+        })
+        .collect()
+}
+
+// Counts the rows from the floor up to the highest block
+pub fn get_stack_height(board: &[Vec<char>]) -> usize {
+    let board_width = get_board_width(board);
+    let floor = board.len() - 1;
+    let highest_row = (0..floor).find(|row| {
+        board[*row][1..board_width - 1]
+            .iter()
+            .any(|cell| *cell != EMPTY)
+    });
+    match highest_row {
+        Some(row) => floor - row,
+        None => 0,
+    }
 }
 
-pub fn collapse_full_row(board: &mut [[char; BOARD_WIDTH]; BOARD_HEIGHT], column: &usize) {
+pub fn collapse_full_row(board: &mut [Vec<char>], column: &usize) {
+    let board_width = get_board_width(board);
     for i in (1..column + 1).rev() {
-        for j in 1..BOARD_WIDTH - 1 {
+        for j in 1..board_width - 1 {
             board[i][j] = board[i - 1][j];
         }
     }
@@ -296,7 +397,7 @@ pub fn game_over(tetromino_positions: &[Coordinate; TETROMINO_SIZE]) -> bool {
 
 fn is_direction_empty(
     tetromino_positions: &[Coordinate; TETROMINO_SIZE],
-    board: &[[char; BOARD_WIDTH]; BOARD_HEIGHT],
+    board: &[Vec<char>],
     direction_value: isize,
 ) -> bool {
     let mut allowed_to_move = false;
@@ -331,7 +432,7 @@ fn move_to_direction(tetromino_positions: &mut [Coordinate; TETROMINO_SIZE], dir
 
 fn is_allowed_to_drop_tetromino(
     tetromino_positions: &[Coordinate; TETROMINO_SIZE],
-    board: &[[char; BOARD_WIDTH]; BOARD_HEIGHT],
+    board: &[Vec<char>],
 ) -> bool {
     let mut allowed_to_drop = false;
     for i in 0..TETROMINO_SIZE {
@@ -0,0 +1,130 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::{
+    board::{MAX_BOARD_HEIGHT, MAX_BOARD_WIDTH, MIN_BOARD_HEIGHT, MIN_BOARD_WIDTH},
+    engine::MAX_LEVEL,
+    mode::GameMode,
+};
+
+#[derive(Debug, Parser)]
+#[command(name = "tetris", version, about = "Tetris in the terminal")]
+pub struct Cli {
+    #[arg(long, global = true, value_parser = parse_mode, help = "Game mode selected in the menu")]
+    pub mode: Option<GameMode>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Seed for the piece sequence, random when not given"
+    )]
+    pub seed: Option<u64>,
+
+    #[arg(long, global = true, value_parser = parse_level, help = "Level to start marathon at")]
+    pub level: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Keep playing marathon past the final level"
+    )]
+    pub endless: bool,
+
+    #[arg(long, global = true, value_parser = parse_width, help = "Number of playable columns")]
+    pub width: Option<usize>,
+
+    #[arg(long, global = true, value_parser = parse_height, help = "Number of playable rows")]
+    pub height: Option<usize>,
+
+    #[arg(long, help = "Do not open an audio device")]
+    pub no_audio: bool,
+
+    #[arg(long, value_name = "PATH", help = "Music file to play")]
+    pub music: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Config file to use instead of the default"
+    )]
+    pub config: Option<PathBuf>,
+
+    #[arg(long, help = "Continue the suspended game")]
+    pub resume: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "resume",
+        help = "Watch a saved replay, same as the replay command"
+    )]
+    pub replay: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    #[command(about = "Watch a saved replay")]
+    Replay { file: PathBuf },
+
+    #[command(
+        name = "verify-replay",
+        alias = "verify",
+        about = "Re-simulate replays and compare them with their recorded results"
+    )]
+    Verify {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    #[command(about = "Render the music, or the sound effects of a replay, to a WAV file")]
+    Render {
+        output: PathBuf,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "Replay to render the sound effects of"
+        )]
+        replay: Option<PathBuf>,
+
+        #[arg(
+            long,
+            default_value_t = 30.0,
+            help = "Length of the rendered music in seconds"
+        )]
+        seconds: f64,
+    },
+
+    #[command(about = "Measure how fast the engine simulates games")]
+    Bench {
+        #[arg(long, default_value_t = 100, help = "Number of games to simulate")]
+        games: usize,
+    },
+}
+
+fn parse_mode(value: &str) -> Result<GameMode, String> {
+    GameMode::from_name(value).ok_or_else(|| format!("unknown mode {}", value))
+}
+
+fn parse_level(value: &str) -> Result<usize, String> {
+    parse_in_range(value, 1, MAX_LEVEL)
+}
+
+fn parse_width(value: &str) -> Result<usize, String> {
+    parse_in_range(value, MIN_BOARD_WIDTH, MAX_BOARD_WIDTH)
+}
+
+fn parse_height(value: &str) -> Result<usize, String> {
+    parse_in_range(value, MIN_BOARD_HEIGHT, MAX_BOARD_HEIGHT)
+}
+
+fn parse_in_range(value: &str, min: usize, max: usize) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(number) if (min..=max).contains(&number) => Ok(number),
+        _ => Err(format!("must be a number from {} to {}", min, max)),
+    }
+}
@@ -0,0 +1,323 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    board::{
+        DEFAULT_BOARD_SIZE, MAX_BOARD_HEIGHT, MAX_BOARD_WIDTH, MIN_BOARD_HEIGHT, MIN_BOARD_WIDTH,
+    },
+    engine::MAX_LEVEL,
+    handling::{Handling, MAX_ARR_MILLISECONDS, MAX_DAS_MILLISECONDS},
+    input::{is_valid_key_name, KeyBindings, BINDABLE_COMMANDS},
+    mode::GameMode,
+    paths::get_config_directory,
+};
+
+const CONFIG_FILENAME: &str = "config.toml";
+const MAX_SPEED_STEP: f64 = 1.0;
+const MAX_TEMPO: f64 = 3.0;
+pub const MAX_VOLUME: u8 = 100;
+const MIN_TERMINAL_WIDTH: u16 = 40;
+const MIN_TERMINAL_HEIGHT: u16 = 10;
+const MAX_TERMINAL_SIZE: u16 = 500;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
+    InvalidKey(String, String),
+    OutOfRange(&'static str, u64, u64, u64),
+    InvalidValue(&'static str, String),
+    NoConfigDirectory,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "Failed to access config file: {}", error),
+            ConfigError::Parse(error) => write!(f, "Failed to parse config file: {}", error),
+            ConfigError::Serialize(error) => write!(f, "Failed to write config file: {}", error),
+            ConfigError::InvalidKey(name, key) => write!(f, "{}: unknown key name {}", name, key),
+            ConfigError::OutOfRange(name, value, min, max) => write!(
+                f,
+                "{} is {}, but must be from {} to {}",
+                name, value, min, max
+            ),
+            ConfigError::InvalidValue(name, message) => write!(f, "{}: {}", name, message),
+            ConfigError::NoConfigDirectory => write!(f, "Could not find a config directory"),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io(error) => Some(error),
+            ConfigError::Parse(error) => Some(error),
+            ConfigError::Serialize(error) => Some(error),
+            ConfigError::InvalidKey(..) => None,
+            ConfigError::OutOfRange(..) => None,
+            ConfigError::InvalidValue(..) => None,
+            ConfigError::NoConfigDirectory => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(error: std::io::Error) -> Self {
+        ConfigError::Io(error)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(error: toml::de::Error) -> Self {
+        ConfigError::Parse(error)
+    }
+}
+
+impl From<toml::ser::Error> for ConfigError {
+    fn from(error: toml::ser::Error) -> Self {
+        ConfigError::Serialize(error)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    pub mode: String,
+    pub start_level: usize,
+    // Marathon keeps going after the final level
+    pub endless: bool,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            mode: String::from(GameMode::Marathon.get_name()),
+            start_level: 1,
+            endless: false,
+            width: DEFAULT_BOARD_SIZE.width,
+            height: DEFAULT_BOARD_SIZE.height,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+    pub music: bool,
+    // Searched for in the data directory and next to the executable when not set
+    pub music_file: Option<PathBuf>,
+    // Added to the music tempo on every level up
+    pub speed_step: f64,
+    // The tempo the music stops speeding up at
+    pub max_tempo: f64,
+    // In percent
+    pub music_volume: u8,
+    pub effects_volume: u8,
+    // More music files to choose from in the options
+    pub playlist: Vec<PathBuf>,
+    // Name of the selected track, the first one in the playlist is played when not set
+    pub track: Option<String>,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            music: true,
+            music_file: None,
+            speed_step: 0.1,
+            max_tempo: 1.5,
+            music_volume: MAX_VOLUME,
+            effects_volume: MAX_VOLUME,
+            playlist: Vec::new(),
+            track: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    pub animations: bool,
+    pub terminal_width: u16,
+    pub terminal_height: u16,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            animations: true,
+            terminal_width: 100,
+            terminal_height: 25,
+        }
+    }
+}
+
+// Every section falls back to its defaults, so a config file only needs the keys it changes.
+// Misspelled keys are errors instead of being ignored
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub game: GameConfig,
+    #[serde(default)]
+    pub audio: AudioConfig,
+    #[serde(default)]
+    pub display: DisplayConfig,
+    #[serde(default)]
+    pub keys: KeyBindings,
+    #[serde(default)]
+    pub handling: Handling,
+}
+
+pub fn load_config(path: &Path) -> Result<Config, ConfigError> {
+    if !path.exists() {
+        let config = Config::default();
+        save_config(&config, path)?;
+        return Ok(config);
+    }
+    let contents = fs::read_to_string(path)?;
+    let config: Config = toml::from_str(&contents)?;
+    validate_config(&config)?;
+    Ok(config)
+}
+
+pub fn save_config(config: &Config, path: &Path) -> Result<(), ConfigError> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    fs::write(path, toml::to_string_pretty(config)?)?;
+    Ok(())
+}
+
+pub fn get_default_config_path() -> Result<PathBuf, ConfigError> {
+    match get_config_directory() {
+        Some(directory) => Ok(directory.join(CONFIG_FILENAME)),
+        None => Err(ConfigError::NoConfigDirectory),
+    }
+}
+
+fn validate_config(config: &Config) -> Result<(), ConfigError> {
+    if GameMode::from_name(&config.game.mode).is_none() {
+        return Err(ConfigError::InvalidValue(
+            "game.mode",
+            format!("unknown mode {}", config.game.mode),
+        ));
+    }
+    check_range(
+        "game.start_level",
+        config.game.start_level as u64,
+        1,
+        MAX_LEVEL as u64,
+    )?;
+    check_range(
+        "game.width",
+        config.game.width as u64,
+        MIN_BOARD_WIDTH as u64,
+        MAX_BOARD_WIDTH as u64,
+    )?;
+    check_range(
+        "game.height",
+        config.game.height as u64,
+        MIN_BOARD_HEIGHT as u64,
+        MAX_BOARD_HEIGHT as u64,
+    )?;
+    if !(0.0..=MAX_SPEED_STEP).contains(&config.audio.speed_step) {
+        return Err(ConfigError::InvalidValue(
+            "audio.speed_step",
+            format!(
+                "{} is not a number from 0 to {}",
+                config.audio.speed_step, MAX_SPEED_STEP
+            ),
+        ));
+    }
+    if !(1.0..=MAX_TEMPO).contains(&config.audio.max_tempo) {
+        return Err(ConfigError::InvalidValue(
+            "audio.max_tempo",
+            format!(
+                "{} is not a number from 1 to {}",
+                config.audio.max_tempo, MAX_TEMPO
+            ),
+        ));
+    }
+    check_range(
+        "audio.music_volume",
+        config.audio.music_volume as u64,
+        0,
+        MAX_VOLUME as u64,
+    )?;
+    check_range(
+        "audio.effects_volume",
+        config.audio.effects_volume as u64,
+        0,
+        MAX_VOLUME as u64,
+    )?;
+    check_range(
+        "display.terminal_width",
+        config.display.terminal_width as u64,
+        MIN_TERMINAL_WIDTH as u64,
+        MAX_TERMINAL_SIZE as u64,
+    )?;
+    check_range(
+        "display.terminal_height",
+        config.display.terminal_height as u64,
+        MIN_TERMINAL_HEIGHT as u64,
+        MAX_TERMINAL_SIZE as u64,
+    )?;
+    for command in BINDABLE_COMMANDS {
+        for key in config.keys.get_keys(&command) {
+            if !is_valid_key_name(key) {
+                return Err(ConfigError::InvalidKey(
+                    format!("keys.{}", command.get_config_name()),
+                    key.clone(),
+                ));
+            }
+        }
+    }
+    check_range(
+        "handling.das_ms",
+        config.handling.das_ms,
+        0,
+        MAX_DAS_MILLISECONDS,
+    )?;
+    check_range(
+        "handling.arr_ms",
+        config.handling.arr_ms,
+        0,
+        MAX_ARR_MILLISECONDS,
+    )
+}
+
+fn check_range(name: &'static str, value: u64, min: u64, max: u64) -> Result<(), ConfigError> {
+    if value < min || value > max {
+        return Err(ConfigError::OutOfRange(name, value, min, max));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_keys_use_defaults() {
+        let config: Config = toml::from_str("[game]\nmode = \"Sprint\"\n").unwrap();
+        assert_eq!(config.game.mode, "Sprint");
+        assert_eq!(config.game.start_level, 1);
+        assert!(validate_config(&config).is_ok());
+    }
+
+    #[test]
+    fn misspelled_keys_are_rejected() {
+        let error = toml::from_str::<Config>("[game]\nmod = \"Sprint\"\n").unwrap_err();
+        assert!(error.to_string().contains("unknown field `mod`"));
+        assert!(toml::from_str::<Config>("[keys]\nhard_dorp = [\"x\"]\n").is_err());
+        assert!(toml::from_str::<Config>("[sound]\nmusic = false\n").is_err());
+    }
+}
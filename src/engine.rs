@@ -0,0 +1,570 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::{
+    board::{
+        clear_previous_tetromino_from_board, collapse_full_row, copy_tetromino_positions,
+        drop_tetromino, game_over, get_board_width, get_full_rows, get_stack_height,
+        get_t_corner_count, init_board, init_tetromino_to_board, write_tetromino_to_board, Board,
+        BoardSize, Coordinate, DEFAULT_BOARD_SIZE,
+    },
+    events::GameEvent,
+    handling::{AutoShift, Handling},
+    input::{commit_action, InputCommand, KeyAction},
+    mode::GameMode,
+    scoring::{
+        get_clear_points, get_combo_points, is_difficult_clear, HARD_DROP_POINTS, SOFT_DROP_POINTS,
+    },
+    stats::GameStats,
+    tetromino::{Randomizer, SBAR1, SBAR2, TBAR, TETROMINO_SIZE},
+};
+
+pub const FRAMES_PER_SECOND: u64 = 60;
+pub const FRAME_DURATION: Duration = Duration::from_micros(1_000_000 / FRAMES_PER_SECOND);
+
+// Frames per row at each level, the guideline's (0.8 - (level - 1) * 0.007)^(level - 1)
+// seconds rounded to frames. A piece falls at most one row per frame
+const GRAVITY_FRAMES: [u64; 15] = [60, 48, 37, 28, 21, 16, 11, 8, 6, 4, 3, 2, 1, 1, 1];
+// Levels past the table keep its last speed
+pub const MAX_LEVEL: usize = GRAVITY_FRAMES.len();
+const LINES_PER_LEVEL: usize = 10;
+const T_SPIN_CORNERS: usize = 3;
+// The stack is in danger when there are no more free rows than this above it
+const DANGER_ROWS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameEnd {
+    ToppedOut,
+    Quit,
+    // The mode's line goal was reached or its time ran out
+    Finished,
+    // Set by the game loop after the game was saved to be resumed later
+    Suspended,
+}
+
+// Everything besides the inputs that decides how a game plays out
+#[derive(Debug, Clone)]
+pub struct Ruleset {
+    pub seed: u64,
+    pub mode: GameMode,
+    pub handling: Handling,
+    pub key_release_events: bool,
+    pub board_size: BoardSize,
+    pub start_level: usize,
+    // Keeps going past the mode's final level
+    pub endless: bool,
+}
+
+impl Ruleset {
+    // Default handling, board and start level, the rest is filled in by the caller
+    pub fn new(seed: u64, mode: GameMode) -> Ruleset {
+        Ruleset {
+            seed,
+            mode,
+            handling: Handling::default(),
+            key_release_events: false,
+            board_size: DEFAULT_BOARD_SIZE,
+            start_level: 1,
+            endless: false,
+        }
+    }
+}
+
+// A snapshot of everything an engine needs to continue a game,
+// the board and tetromino are stored as one string per row and the randomizer state
+// as hex, since TOML integers cannot hold every u64
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineState {
+    pub randomizer: String,
+    pub board: Vec<String>,
+    pub tetromino: Vec<String>,
+    pub tetromino_positions: Vec<Coordinate>,
+    pub tetromino_active: bool,
+    pub pending_rows: Vec<usize>,
+    pub tetrominos_dropped: usize,
+    pub stats: GameStats,
+    pub frame: u64,
+    pub gravity_counter: u64,
+    pub soft_drop: bool,
+    pub auto_shift: AutoShift,
+    pub t_tetromino: bool,
+    pub rotated_last: bool,
+    pub danger: bool,
+    pub back_to_back: bool,
+}
+
+pub struct Engine {
+    ruleset: Ruleset,
+    randomizer: Randomizer,
+    board: Board,
+    tetromino: [[char; TETROMINO_SIZE]; TETROMINO_SIZE],
+    tetromino_positions: [Coordinate; TETROMINO_SIZE],
+    tetromino_active: bool,
+    pending_rows: Vec<usize>,
+    tetrominos_dropped: usize,
+    stats: GameStats,
+    frame: u64,
+    gravity_counter: u64,
+    soft_drop: bool,
+    auto_shift: AutoShift,
+    // T-spins need to know the shape that was spawned and whether the last move was a rotation
+    t_tetromino: bool,
+    rotated_last: bool,
+    danger: bool,
+    // The last clear was a difficult one, for guideline scoring
+    back_to_back: bool,
+    game_end: Option<GameEnd>,
+    events: Vec<GameEvent>,
+}
+
+impl Engine {
+    pub fn new(ruleset: &Ruleset) -> Engine {
+        let mut engine = Engine {
+            ruleset: ruleset.clone(),
+            randomizer: Randomizer::new(ruleset.seed),
+            board: init_board(&ruleset.board_size),
+            tetromino: [[' '; TETROMINO_SIZE]; TETROMINO_SIZE],
+            tetromino_positions: [Coordinate::default(); TETROMINO_SIZE],
+            tetromino_active: false,
+            pending_rows: Vec::new(),
+            tetrominos_dropped: 0,
+            stats: GameStats::new(ruleset.start_level),
+            frame: 0,
+            gravity_counter: 0,
+            soft_drop: false,
+            auto_shift: AutoShift::new(&ruleset.handling),
+            t_tetromino: false,
+            rotated_last: false,
+            danger: false,
+            back_to_back: false,
+            game_end: None,
+            events: Vec::new(),
+        };
+        engine.spawn_tetromino();
+        engine
+    }
+
+    // Returns None when the state does not fit the board or tetromino size
+    pub fn from_state(ruleset: &Ruleset, state: EngineState) -> Option<Engine> {
+        let tetromino_positions: [Coordinate; TETROMINO_SIZE] =
+            state.tetromino_positions.try_into().ok()?;
+        let board = init_board(&ruleset.board_size);
+        if state.pending_rows.iter().any(|row| *row >= board.len()) {
+            return None;
+        }
+        let mut tetromino = [[' '; TETROMINO_SIZE]; TETROMINO_SIZE];
+        let tetromino_rows = parse_rows(&state.tetromino, TETROMINO_SIZE, TETROMINO_SIZE)?;
+        for (row, parsed_row) in tetromino.iter_mut().zip(tetromino_rows) {
+            row.copy_from_slice(&parsed_row);
+        }
+        let randomizer_state = u64::from_str_radix(&state.randomizer, 16).ok()?;
+        Some(Engine {
+            ruleset: ruleset.clone(),
+            randomizer: Randomizer::from_state(randomizer_state)?,
+            board: parse_rows(&state.board, get_board_width(&board), board.len())?,
+            tetromino,
+            tetromino_positions,
+            tetromino_active: state.tetromino_active,
+            pending_rows: state.pending_rows,
+            tetrominos_dropped: state.tetrominos_dropped,
+            stats: state.stats,
+            frame: state.frame,
+            gravity_counter: state.gravity_counter,
+            soft_drop: state.soft_drop,
+            auto_shift: state.auto_shift,
+            t_tetromino: state.t_tetromino,
+            rotated_last: state.rotated_last,
+            danger: state.danger,
+            back_to_back: state.back_to_back,
+            game_end: None,
+            events: Vec::new(),
+        })
+    }
+
+    pub fn get_state(&self) -> EngineState {
+        EngineState {
+            randomizer: format!("{:016x}", self.randomizer.get_state()),
+            board: format_rows(&self.board),
+            tetromino: format_rows(&self.tetromino.map(Vec::from)),
+            tetromino_positions: self.tetromino_positions.to_vec(),
+            tetromino_active: self.tetromino_active,
+            pending_rows: self.pending_rows.clone(),
+            tetrominos_dropped: self.tetrominos_dropped,
+            stats: self.stats.clone(),
+            frame: self.frame,
+            gravity_counter: self.gravity_counter,
+            soft_drop: self.soft_drop,
+            auto_shift: self.auto_shift.clone(),
+            t_tetromino: self.t_tetromino,
+            rotated_last: self.rotated_last,
+            danger: self.danger,
+            back_to_back: self.back_to_back,
+        }
+    }
+
+    pub fn get_frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn get_board(&self) -> &[Vec<char>] {
+        &self.board
+    }
+
+    // Left and right keys that have been pressed but not released
+    pub fn get_held_directions(&self) -> &[InputCommand] {
+        self.auto_shift.get_held()
+    }
+
+    pub fn get_stats(&self) -> &GameStats {
+        &self.stats
+    }
+
+    pub fn is_in_danger(&self) -> bool {
+        self.danger
+    }
+
+    pub fn get_game_end(&self) -> Option<GameEnd> {
+        self.game_end
+    }
+
+    pub fn take_events(&mut self) -> Vec<GameEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    // Returns true when the board needs to be redrawn
+    pub fn handle_input(&mut self, command: InputCommand, action: KeyAction) -> bool {
+        if self.game_end.is_some() {
+            return false;
+        }
+        match (command, action) {
+            (_, KeyAction::Release) => {
+                self.auto_shift.release(command);
+                false
+            }
+            // Held keys are repeated by DAS and ARR, not by the terminal
+            (
+                InputCommand::Left
+                | InputCommand::Right
+                | InputCommand::RotateCw
+                | InputCommand::RotateCcw
+                | InputCommand::Rotate180
+                | InputCommand::HardDrop,
+                KeyAction::Repeat,
+            ) => false,
+            // Suspending and muting are handled by the game loop, which owns the save file
+            // and the audio
+            (InputCommand::Suspend | InputCommand::Mute, _) => false,
+            (InputCommand::Exit, _) => {
+                self.game_end = Some(GameEnd::Quit);
+                true
+            }
+            _ if !self.tetromino_active => false,
+            (InputCommand::HardDrop, _) => {
+                self.hard_drop();
+                true
+            }
+            _ => {
+                self.commit(&command);
+                match command {
+                    InputCommand::Left | InputCommand::Right if self.ruleset.key_release_events => {
+                        self.auto_shift.press(command);
+                    }
+                    InputCommand::Down => self.soft_drop = true,
+                    _ => {}
+                }
+                true
+            }
+        }
+    }
+
+    // Advances the game by one frame, returns true when the board needs to be redrawn
+    pub fn tick(&mut self) -> bool {
+        if self.game_end.is_some() {
+            return false;
+        }
+        self.frame += 1;
+        self.stats.elapsed = Duration::from_millis(frames_to_milliseconds(self.frame));
+        if let Some(time_limit) = self.ruleset.mode.get_time_limit() {
+            if self.stats.elapsed >= time_limit {
+                self.stats.elapsed = time_limit;
+                self.game_end = Some(GameEnd::Finished);
+                return true;
+            }
+        }
+
+        // Rows cleared by the last lock stay visible for one frame before collapsing
+        if !self.tetromino_active {
+            for row in std::mem::take(&mut self.pending_rows) {
+                collapse_full_row(&mut self.board, &row);
+            }
+            self.update_danger();
+            self.spawn_tetromino();
+            return true;
+        }
+
+        let mut changed = false;
+        if let Some((direction, shifts)) = self.auto_shift.tick() {
+            for _ in 0..shifts.min(get_board_width(&self.board)) {
+                self.commit(&direction);
+            }
+            changed = true;
+        }
+
+        self.gravity_counter += 1;
+        if self.soft_drop || self.gravity_counter >= self.get_gravity_frames() {
+            let soft_drop = std::mem::take(&mut self.soft_drop);
+            self.gravity_counter = 0;
+            if !self.drop() {
+                self.lock_tetromino();
+            } else if soft_drop {
+                self.add_drop_points(SOFT_DROP_POINTS);
+            }
+            changed = true;
+        }
+        changed
+    }
+
+    fn commit(&mut self, command: &InputCommand) {
+        let previous_tetromino = self.tetromino;
+        let previous_tetromino_positions = self.tetromino_positions;
+        commit_action(
+            command,
+            &mut self.tetromino_positions,
+            &mut self.tetromino,
+            &mut self.board,
+        );
+        write_tetromino_to_board(&self.tetromino_positions, &mut self.board);
+
+        if self.tetromino == previous_tetromino
+            && self.tetromino_positions == previous_tetromino_positions
+        {
+            return;
+        }
+        match command {
+            InputCommand::Left | InputCommand::Right => {
+                self.rotated_last = false;
+                self.events.push(GameEvent::Moved);
+            }
+            InputCommand::RotateCw | InputCommand::RotateCcw | InputCommand::Rotate180 => {
+                self.rotated_last = true;
+                self.events.push(GameEvent::Rotated);
+            }
+            _ => {}
+        }
+    }
+
+    // Moves the tetromino down by one row, returns false when it is resting on something
+    fn drop(&mut self) -> bool {
+        let previous_tetromino_positions = copy_tetromino_positions(&self.tetromino_positions);
+        if !drop_tetromino(&mut self.tetromino_positions, &self.board) {
+            return false;
+        }
+        clear_previous_tetromino_from_board(&previous_tetromino_positions, &mut self.board);
+        write_tetromino_to_board(&self.tetromino_positions, &mut self.board);
+        self.rotated_last = false;
+        true
+    }
+
+    fn hard_drop(&mut self) {
+        while self.drop() {
+            self.add_drop_points(HARD_DROP_POINTS);
+        }
+        self.events.push(GameEvent::HardDropped);
+        self.lock_tetromino();
+    }
+
+    fn add_drop_points(&mut self, points: usize) {
+        if self.ruleset.mode.uses_guideline_scoring() {
+            self.stats.points += points;
+        }
+    }
+
+    fn get_gravity_frames(&self) -> u64 {
+        GRAVITY_FRAMES[self.stats.level.min(MAX_LEVEL) - 1]
+    }
+
+    fn spawn_tetromino(&mut self) {
+        let mut tetromino = self.randomizer.next_tetromino();
+        while (tetromino == SBAR1 || tetromino == SBAR2) && self.tetrominos_dropped == 0 {
+            tetromino = self.randomizer.next_tetromino();
+        }
+        self.tetromino = tetromino;
+        self.t_tetromino = tetromino == TBAR;
+        self.rotated_last = false;
+        let start_x = (get_board_width(&self.board) - TETROMINO_SIZE) / 2;
+        self.tetromino_positions =
+            init_tetromino_to_board(&mut self.board, &tetromino, start_x as isize, 0);
+        self.tetromino_active = true;
+        self.gravity_counter = 0;
+    }
+
+    // Only locked blocks count, so this runs before the next tetromino spawns
+    fn update_danger(&mut self) {
+        let free_rows = (self.board.len() - 1).saturating_sub(get_stack_height(&self.board));
+        let danger = free_rows <= DANGER_ROWS;
+        if danger != self.danger {
+            self.danger = danger;
+            self.events.push(GameEvent::Danger(danger));
+        }
+    }
+
+    // The level goes up every ten lines from the start level,
+    // completing the mode's final level finishes the game
+    fn update_level(&mut self) {
+        let level = self.ruleset.start_level + self.stats.lines / LINES_PER_LEVEL;
+        if level <= self.stats.level {
+            return;
+        }
+        if let Some(final_level) = self.ruleset.mode.get_final_level() {
+            if level > final_level && !self.ruleset.endless {
+                self.game_end = Some(GameEnd::Finished);
+                return;
+            }
+        }
+        self.stats.level = level;
+        self.events.push(GameEvent::LevelUp(level));
+    }
+
+    // Only clears break a back-to-back streak, locking without clearing keeps it
+    fn add_guideline_points(&mut self, lines: usize, t_spin: bool, level: usize) {
+        let difficult = is_difficult_clear(lines, t_spin);
+        let back_to_back = difficult && self.back_to_back;
+        self.stats.points += get_clear_points(lines, t_spin, back_to_back, level);
+        if lines > 0 {
+            self.stats.points += get_combo_points(self.stats.get_combo(), level);
+            self.back_to_back = difficult;
+        }
+    }
+
+    fn lock_tetromino(&mut self) {
+        write_tetromino_to_board(&self.tetromino_positions, &mut self.board);
+        self.tetromino_active = false;
+        self.events
+            .push(GameEvent::PieceLocked(self.tetromino_positions));
+
+        let full_rows = get_full_rows(&self.board);
+        let t_spin = self.t_tetromino
+            && self.rotated_last
+            && get_t_corner_count(&self.tetromino_positions, &self.board) >= T_SPIN_CORNERS;
+        if t_spin {
+            self.stats.t_spins += 1;
+            self.events.push(GameEvent::TSpin(full_rows.len()));
+        }
+        // Guideline points use the level the piece was locked at
+        let level = self.stats.level;
+        if !full_rows.is_empty() {
+            if !self.ruleset.mode.uses_guideline_scoring() {
+                self.stats.points += 1 + full_rows.len();
+            }
+            self.events.push(GameEvent::LinesCleared(full_rows.clone()));
+        }
+        self.stats.record_lock(full_rows.len());
+        if self.ruleset.mode.uses_guideline_scoring() {
+            self.add_guideline_points(full_rows.len(), t_spin, level);
+        }
+        self.pending_rows = full_rows;
+        if let Some(split_lines) = self.ruleset.mode.get_split_lines() {
+            self.stats.record_splits(split_lines);
+        }
+        self.update_level();
+        if let Some(line_goal) = self.ruleset.mode.get_line_goal() {
+            if self.stats.lines >= line_goal {
+                self.game_end = Some(GameEnd::Finished);
+            }
+        }
+        if self.game_end.is_some() {
+            return;
+        }
+
+        if game_over(&self.tetromino_positions) && self.tetrominos_dropped > 0 {
+            self.game_end = Some(GameEnd::ToppedOut);
+            self.events.push(GameEvent::ToppedOut);
+        }
+        self.tetrominos_dropped += 1;
+    }
+}
+
+fn format_rows(rows: &[Vec<char>]) -> Vec<String> {
+    rows.iter().map(|row| row.iter().collect()).collect()
+}
+
+fn parse_rows(rows: &[String], width: usize, height: usize) -> Option<Vec<Vec<char>>> {
+    if rows.len() != height || rows.iter().any(|row| row.chars().count() != width) {
+        return None;
+    }
+    Some(rows.iter().map(|row| row.chars().collect()).collect())
+}
+
+pub fn milliseconds_to_frames(milliseconds: u64) -> u64 {
+    (milliseconds * FRAMES_PER_SECOND + 500) / 1000
+}
+
+// FRAME_DURATION is rounded down, so the elapsed time is computed from the frame count instead
+pub fn frames_to_milliseconds(frames: u64) -> u64 {
+    (frames * 1000 + FRAMES_PER_SECOND / 2) / FRAMES_PER_SECOND
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elapsed_time_does_not_drift() {
+        assert_eq!(frames_to_milliseconds(60 * 60), 60_000);
+        assert_eq!(frames_to_milliseconds(1), 17);
+        assert_eq!(frames_to_milliseconds(2), 33);
+    }
+
+    #[test]
+    fn ultra_finishes_at_time_limit() {
+        let ruleset = Ruleset::new(1, GameMode::Ultra);
+        let mut engine = Engine::new(&ruleset);
+        let time_limit = GameMode::Ultra.get_time_limit().unwrap();
+        let limit_frames = milliseconds_to_frames(time_limit.as_millis() as u64);
+        // Skip ahead, an idle game tops out before the time runs out
+        engine.frame = limit_frames - 2;
+        engine.tick();
+        assert_eq!(engine.get_game_end(), None);
+        engine.tick();
+        assert_eq!(engine.get_game_end(), Some(GameEnd::Finished));
+        assert_eq!(engine.get_stats().elapsed, time_limit);
+    }
+
+    const ROUND_TRIP_INPUTS: [InputCommand; 4] = [
+        InputCommand::Left,
+        InputCommand::RotateCw,
+        InputCommand::HardDrop,
+        InputCommand::Right,
+    ];
+
+    const ROUND_TRIP_INTERVAL: u64 = 15;
+
+    fn play_frames(engine: &mut Engine, frames: u64) {
+        for _ in 0..frames {
+            if engine.get_frame().is_multiple_of(ROUND_TRIP_INTERVAL) {
+                let index = (engine.get_frame() / ROUND_TRIP_INTERVAL) as usize;
+                let command = ROUND_TRIP_INPUTS[index % ROUND_TRIP_INPUTS.len()];
+                engine.handle_input(command, KeyAction::Press);
+            }
+            engine.tick();
+        }
+    }
+
+    #[test]
+    fn restored_state_plays_on_identically() {
+        let ruleset = Ruleset::new(5, GameMode::Marathon);
+        let mut engine = Engine::new(&ruleset);
+        play_frames(&mut engine, 200);
+        let saved = toml::to_string(&engine.get_state()).unwrap();
+        let mut restored = Engine::from_state(&ruleset, toml::from_str(&saved).unwrap()).unwrap();
+        assert_eq!(toml::to_string(&restored.get_state()).unwrap(), saved);
+
+        play_frames(&mut engine, 300);
+        play_frames(&mut restored, 300);
+        assert!(engine.get_stats().pieces > 0);
+        assert_eq!(
+            toml::to_string(&restored.get_state()).unwrap(),
+            toml::to_string(&engine.get_state()).unwrap()
+        );
+    }
+}
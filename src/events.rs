@@ -0,0 +1,16 @@
+use crate::{board::Coordinate, tetromino::TETROMINO_SIZE};
+
+#[derive(Debug, Clone)]
+pub enum GameEvent {
+    Moved,
+    Rotated,
+    HardDropped,
+    PieceLocked([Coordinate; TETROMINO_SIZE]),
+    LinesCleared(Vec<usize>),
+    // A T tetromino rotated into a spot with three filled corners, with the lines it cleared
+    TSpin(usize),
+    LevelUp(usize),
+    // Sent when the stack gets close to the top and again when it is back down
+    Danger(bool),
+    ToppedOut,
+}
@@ -1,84 +1,377 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crossterm::{
-    event::poll,
+    event::{KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags},
     execute,
-    terminal::{Clear, ClearType, SetSize},
+    terminal::{supports_keyboard_enhancement, Clear, ClearType},
 };
 
 use crate::{
-    audio::Audio,
-    board::{
-        clear_previous_tetromino_from_board, collapse_full_row, copy_tetromino_positions,
-        drop_tetromino, game_over, is_full_row, write_tetromino_to_board,
-    },
-    input::commit_action,
-    tetromino::{get_random_tetromino, SBAR1, SBAR2},
-};
-use crate::{
-    board::{display_board, init_tetromino_to_board},
-    input::read_user_input,
+    animation::{has_animation, play_event_animation},
+    audio_backend::AudioBackend,
+    board::display_board,
+    engine::{Engine, GameEnd, Ruleset, FRAME_DURATION},
+    events::GameEvent,
+    highscores::HighScore,
+    input::{InputCommand, KeyAction},
+    input_source::{InputSource, TerminalInput},
+    replay::{Replay, ReplayResult},
+    savegame::{save_game, SavedGame},
+    settings::Settings,
+    stats::{format_precise_duration, GameStats},
+    tetromino::generate_seed,
 };
 
-use crate::board::init_board;
-
-pub fn start_game(audio: Option<&Audio>) -> std::io::Result<()> {
-    execute!(std::io::stdout(), SetSize(100, 25), Clear(ClearType::All))?;
-    let mut board = init_board();
-    let mut exit = false;
-    let mut points: usize = 0;
-    let mut time_in_milliseconds = 1000;
-    let mut sound_playback_speed = 1.0;
-    let mut tetrominos_dropped = 0;
-    while !exit {
-        let mut tetromino = get_random_tetromino();
-        if (tetromino == SBAR1 || tetromino == SBAR2) && tetrominos_dropped == 0 {
-            continue;
-        }
-        let mut tetromino_positions = init_tetromino_to_board(&mut board, &tetromino, 4, 0);
-
-        loop {
-            if poll(Duration::from_millis(time_in_milliseconds))? {
-                let input = read_user_input()?;
-                if !commit_action(&input, &mut tetromino_positions, &mut tetromino, &mut board) {
-                    exit = true;
-                    break;
+const DANGER_TEMPO: f32 = 1.25;
+// Timed modes redraw this often so the clock keeps running between moves
+const TIMER_REDRAW_FRAMES: u64 = 6;
+
+pub struct FinishedGame {
+    pub stats: GameStats,
+    pub game_end: GameEnd,
+    pub replay: Replay,
+}
+
+pub fn create_ruleset(settings: &Settings, key_release_events: bool) -> Ruleset {
+    Ruleset {
+        seed: settings.seed.unwrap_or_else(generate_seed),
+        mode: settings.mode,
+        handling: settings.handling,
+        key_release_events,
+        board_size: settings.board_size,
+        start_level: if settings.mode.has_start_level() {
+            settings.start_level
+        } else {
+            1
+        },
+        endless: settings.endless && settings.mode.get_final_level().is_some(),
+    }
+}
+
+pub fn start_game(
+    settings: &Settings,
+    audio: &dyn AudioBackend,
+    personal_best: Option<&HighScore>,
+) -> std::io::Result<FinishedGame> {
+    // Release events are needed for DAS, without them the terminal's key repeat moves pieces
+    let key_release_events = supports_keyboard_enhancement().unwrap_or(false);
+    let ruleset = create_ruleset(settings, key_release_events);
+    let saved_game = SavedGame {
+        engine: Engine::new(&ruleset),
+        replay: Replay::new(&ruleset),
+    };
+    resume_game(settings, audio, saved_game, personal_best)
+}
+
+pub fn resume_game(
+    settings: &Settings,
+    audio: &dyn AudioBackend,
+    saved_game: SavedGame,
+    personal_best: Option<&HighScore>,
+) -> std::io::Result<FinishedGame> {
+    let key_release_events = saved_game.replay.ruleset.key_release_events;
+    if key_release_events {
+        execute!(
+            std::io::stdout(),
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+        )?;
+    }
+    let mut input_source = TerminalInput::new(&settings.key_bindings);
+    let result = run_game(
+        settings,
+        saved_game,
+        audio,
+        &mut input_source,
+        personal_best,
+    );
+    // The menus play the music at its normal tempo
+    audio.set_tempo(1.0);
+    if key_release_events {
+        execute!(std::io::stdout(), PopKeyboardEnhancementFlags)?;
+    }
+    result
+}
+
+fn run_game(
+    settings: &Settings,
+    saved_game: SavedGame,
+    audio: &dyn AudioBackend,
+    input_source: &mut dyn InputSource,
+    personal_best: Option<&HighScore>,
+) -> std::io::Result<FinishedGame> {
+    execute!(std::io::stdout(), Clear(ClearType::All))?;
+    let SavedGame {
+        mut engine,
+        mut replay,
+    } = saved_game;
+    audio.set_tempo(get_music_tempo(settings, &engine));
+    // A resumed game keeps the mode it was started in
+    let shows_timer = replay.ruleset.mode.shows_timer();
+
+    // The engine advances in fixed frames, inputs are applied as soon as they arrive
+    let mut accumulated_time = Duration::ZERO;
+    let mut previous_time = Instant::now();
+    let mut redraw = true;
+    let mut status = String::new();
+    let mut audio_status = get_audio_status(audio);
+    loop {
+        let timeout = FRAME_DURATION.saturating_sub(accumulated_time);
+        if let Some(timed_input) = input_source.next_input(engine.get_frame(), timeout)? {
+            if timed_input.command == InputCommand::Suspend {
+                if timed_input.action == KeyAction::Press {
+                    match save_game(&engine, &replay) {
+                        Ok(()) => {
+                            return Ok(FinishedGame {
+                                stats: engine.get_stats().clone(),
+                                game_end: GameEnd::Suspended,
+                                replay,
+                            })
+                        }
+                        Err(error) => status = error.to_string(),
+                    }
+                    redraw = true;
+                }
+            } else if timed_input.command == InputCommand::Mute {
+                if timed_input.action == KeyAction::Press {
+                    audio.toggle_mute();
+                    audio_status = get_audio_status(audio);
+                    redraw = true;
+                }
+            } else {
+                replay.record(timed_input.frame, timed_input.command, timed_input.action);
+                redraw |= engine.handle_input(timed_input.command, timed_input.action);
+                // Time spent animating is not game time
+                if play_events(settings, audio, &mut engine)? {
+                    previous_time = Instant::now();
                 }
             }
+        }
 
-            let prev_tetromino_positions = copy_tetromino_positions(&tetromino_positions);
-            if !drop_tetromino(&mut tetromino_positions, &board) {
-                write_tetromino_to_board(&tetromino_positions, &mut board);
-                break;
+        let now = Instant::now();
+        accumulated_time += now - previous_time;
+        previous_time = now;
+        while accumulated_time >= FRAME_DURATION {
+            redraw |= engine.tick();
+            redraw |= shows_timer && engine.get_frame() % TIMER_REDRAW_FRAMES == 0;
+            accumulated_time -= FRAME_DURATION;
+            if play_events(settings, audio, &mut engine)? {
+                previous_time = Instant::now();
             }
+        }
+
+        audio.check_output();
+        if get_audio_status(audio) != audio_status {
+            audio_status = get_audio_status(audio);
+            redraw = true;
+        }
 
-            clear_previous_tetromino_from_board(&prev_tetromino_positions, &mut board);
-            write_tetromino_to_board(&tetromino_positions, &mut board);
+        if let Some(game_end) = engine.get_game_end() {
+            replay.result = Some(ReplayResult::from_engine(&engine));
+            return Ok(FinishedGame {
+                stats: engine.get_stats().clone(),
+                game_end,
+                replay,
+            });
+        }
+
+        if redraw {
             execute!(std::io::stdout(), Clear(ClearType::FromCursorUp))?;
-            display_board(&board);
+            display_board(engine.get_board());
+            print!(
+                "{}",
+                get_mode_status(&replay.ruleset, &engine, personal_best)
+            );
+            print!("{}\r\n{}\r\n", status, audio_status);
+            redraw = false;
         }
-        let mut column = is_full_row(&board);
-        if column != 0 {
-            sound_playback_speed += 0.1;
-            if let Some(audio) = audio {
-                audio.get_sink().set_speed(sound_playback_speed);
-            }
-            if time_in_milliseconds > 100 {
-                time_in_milliseconds -= 100;
-            }
-            points += 1;
+    }
+}
+
+// Runs before the next tick, so cleared rows are still on the board when they are animated.
+// Returns true when an animation was played
+fn play_events(
+    settings: &Settings,
+    audio: &dyn AudioBackend,
+    engine: &mut Engine,
+) -> std::io::Result<bool> {
+    let events = engine.take_events();
+    for event in &events {
+        audio.play_event(event);
+        if let GameEvent::LevelUp(_) | GameEvent::Danger(_) = event {
+            audio.set_tempo(get_music_tempo(settings, engine));
         }
-        while column != 0 {
-            collapse_full_row(&mut board, &column);
-            column = is_full_row(&board);
-            points += 1;
+        if settings.animations {
+            play_event_animation(event, engine.get_board())?;
         }
-        if game_over(&tetromino_positions) && tetrominos_dropped > 0 {
-            print!("GAME OVER\r\n");
-            print!("Points: {}\r\n", points);
-            exit = true;
+    }
+    Ok(settings.animations && events.iter().any(has_animation))
+}
+
+// The music speeds up with the level until it reaches the maximum tempo,
+// danger speeds it up beyond that
+fn get_music_tempo(settings: &Settings, engine: &Engine) -> f32 {
+    let level_steps = engine.get_stats().level.saturating_sub(1) as f32;
+    let tempo = (1.0 + settings.music_speed_step * level_steps).min(settings.music_max_tempo);
+    if engine.is_in_danger() {
+        tempo * DANGER_TEMPO
+    } else {
+        tempo
+    }
+}
+
+// Progress towards the mode's goal against the personal best, empty for modes without one
+fn get_mode_status(
+    ruleset: &Ruleset,
+    engine: &Engine,
+    personal_best: Option<&HighScore>,
+) -> String {
+    let mode = &ruleset.mode;
+    let stats = engine.get_stats();
+    if let Some(final_level) = mode.get_final_level() {
+        let mut mode_status = if ruleset.endless {
+            format!("Level {}", stats.level)
+        } else {
+            format!("Level {}/{}", stats.level, final_level)
+        };
+        mode_status.push_str(&format!("  Lines {}  Score {}", stats.lines, stats.points));
+        if let Some(personal_best) = personal_best {
+            mode_status.push_str(&format!("  PB {}", personal_best.points));
+        }
+        mode_status.push_str("\r\n");
+        return mode_status;
+    }
+    if let Some(time_limit) = mode.get_time_limit() {
+        let mut mode_status = format!(
+            "Time left {}  Score {}",
+            format_precise_duration(&time_limit.saturating_sub(stats.elapsed)),
+            stats.points
+        );
+        if let Some(personal_best) = personal_best {
+            mode_status.push_str(&format!("  PB {}", personal_best.points));
         }
-        tetrominos_dropped += 1;
+        mode_status.push_str("\r\n");
+        return mode_status;
+    }
+    let line_goal = match mode.get_line_goal() {
+        Some(line_goal) => line_goal,
+        None => return String::new(),
+    };
+    let mut mode_status = format!(
+        "Lines {}/{}  Time {}",
+        stats.lines.min(line_goal),
+        line_goal,
+        format_precise_duration(&stats.elapsed)
+    );
+    if let Some(personal_best) = personal_best {
+        mode_status.push_str(&format!(
+            "  PB {}",
+            format_precise_duration(&Duration::from_millis(personal_best.time_ms))
+        ));
+    }
+    mode_status.push_str("\r\n");
+    for (index, split) in stats.splits.iter().enumerate() {
+        mode_status.push_str(&format_precise_duration(split));
+        if let Some(best_ms) = personal_best.and_then(|best| best.splits_ms.get(index)) {
+            let delta = split.as_secs_f64() - *best_ms as f64 / 1000.0;
+            mode_status.push_str(&format!(" ({:+.3})", delta));
+        }
+        mode_status.push_str("  ");
+    }
+    mode_status.push_str("\r\n");
+    mode_status
+}
+
+// Shown below the board when the game cannot be heard, unless audio was turned off
+fn get_audio_status(audio: &dyn AudioBackend) -> String {
+    if !audio.is_enabled() {
+        String::new()
+    } else if !audio.is_available() {
+        String::from("Audio unavailable")
+    } else if audio.is_muted() {
+        String::from("Muted")
+    } else {
+        String::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audio_backend::NullAudio,
+        input_source::{ScriptedInput, TimedInput},
+        mode::GameMode,
+    };
+
+    fn press(command: InputCommand) -> TimedInput {
+        TimedInput {
+            frame: 0,
+            command,
+            action: KeyAction::Press,
+        }
+    }
+
+    fn play_scripted_game(inputs: Vec<TimedInput>) -> FinishedGame {
+        let mut settings = Settings::default();
+        settings.seed = Some(1);
+        settings.animations = false;
+        let ruleset = create_ruleset(&settings, false);
+        let saved_game = SavedGame {
+            engine: Engine::new(&ruleset),
+            replay: Replay::new(&ruleset),
+        };
+        let mut input_source = ScriptedInput::new(inputs);
+        run_game(&settings, saved_game, &NullAudio, &mut input_source, None).unwrap()
+    }
+
+    #[test]
+    fn only_marathon_uses_start_level_and_endless() {
+        let mut settings = Settings::default();
+        settings.start_level = 15;
+        settings.endless = true;
+        let ruleset = create_ruleset(&settings, false);
+        assert_eq!((ruleset.start_level, ruleset.endless), (15, true));
+        for mode in [GameMode::Sprint, GameMode::Ultra] {
+            settings.mode = mode;
+            let ruleset = create_ruleset(&settings, false);
+            assert_eq!((ruleset.start_level, ruleset.endless), (1, false));
+        }
+    }
+
+    #[test]
+    fn scripted_inputs_are_played_and_recorded() {
+        let finished_game = play_scripted_game(vec![
+            press(InputCommand::Left),
+            press(InputCommand::HardDrop),
+        ]);
+        assert_eq!(finished_game.game_end, GameEnd::Quit);
+        assert_eq!(finished_game.stats.pieces, 1);
+        let commands: Vec<InputCommand> = finished_game
+            .replay
+            .inputs
+            .iter()
+            .map(|input| input.command)
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                InputCommand::Left,
+                InputCommand::HardDrop,
+                InputCommand::Exit
+            ]
+        );
+        assert!(finished_game.replay.result.is_some());
+    }
+
+    #[test]
+    fn disabled_audio_is_not_reported() {
+        assert_eq!(get_audio_status(&NullAudio), "");
+    }
+
+    #[test]
+    fn game_loop_keys_are_not_recorded() {
+        let finished_game = play_scripted_game(vec![press(InputCommand::Mute)]);
+        assert_eq!(finished_game.game_end, GameEnd::Quit);
+        assert_eq!(finished_game.replay.inputs.len(), 1);
     }
-    Ok(())
 }
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{engine::milliseconds_to_frames, input::InputCommand};
+
+pub const MAX_DAS_MILLISECONDS: u64 = 500;
+pub const MAX_ARR_MILLISECONDS: u64 = 200;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Handling {
+    pub das_ms: u64,
+    pub arr_ms: u64,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Handling {
+            das_ms: 167,
+            arr_ms: 33,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoShift {
+    // Held directions in the order they were pressed, the last one shifts
+    held: Vec<InputCommand>,
+    held_frames: u64,
+    delay_frames: u64,
+    repeat_frames: u64,
+}
+
+impl AutoShift {
+    pub fn new(handling: &Handling) -> AutoShift {
+        AutoShift {
+            held: Vec::new(),
+            held_frames: 0,
+            delay_frames: milliseconds_to_frames(handling.das_ms),
+            repeat_frames: milliseconds_to_frames(handling.arr_ms),
+        }
+    }
+
+    pub fn press(&mut self, direction: InputCommand) {
+        self.held.retain(|held| *held != direction);
+        self.held.push(direction);
+        self.held_frames = 0;
+    }
+
+    // Releasing the shifting direction falls back to the other one if it is still held
+    pub fn release(&mut self, direction: InputCommand) {
+        if self.held.last() == Some(&direction) {
+            self.held_frames = 0;
+        }
+        self.held.retain(|held| *held != direction);
+    }
+
+    pub fn get_held(&self) -> &[InputCommand] {
+        &self.held
+    }
+
+    pub fn tick(&mut self) -> Option<(InputCommand, usize)> {
+        let direction = *self.held.last()?;
+        self.held_frames += 1;
+        if self.held_frames < self.delay_frames {
+            return None;
+        }
+        // Zero ARR moves the piece all the way to the wall at once,
+        // the engine stops shifting once the piece hits something
+        if self.repeat_frames == 0 {
+            return Some((direction, usize::MAX));
+        }
+        if (self.held_frames - self.delay_frames).is_multiple_of(self.repeat_frames) {
+            return Some((direction, 1));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 10 frames of DAS and 2 frames of ARR
+    const HANDLING: Handling = Handling {
+        das_ms: 167,
+        arr_ms: 33,
+    };
+
+    fn get_shift_frames(
+        auto_shift: &mut AutoShift,
+        frames: u64,
+    ) -> Vec<(u64, InputCommand, usize)> {
+        (1..=frames)
+            .filter_map(|frame| {
+                auto_shift
+                    .tick()
+                    .map(|(direction, shifts)| (frame, direction, shifts))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shifts_after_das_then_every_arr() {
+        let mut auto_shift = AutoShift::new(&HANDLING);
+        auto_shift.press(InputCommand::Left);
+        assert_eq!(
+            get_shift_frames(&mut auto_shift, 15),
+            [
+                (10, InputCommand::Left, 1),
+                (12, InputCommand::Left, 1),
+                (14, InputCommand::Left, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn zero_arr_shifts_to_the_wall() {
+        let mut auto_shift = AutoShift::new(&Handling {
+            arr_ms: 0,
+            ..HANDLING
+        });
+        auto_shift.press(InputCommand::Right);
+        let shifts = get_shift_frames(&mut auto_shift, 11);
+        assert_eq!(
+            shifts[..2],
+            [
+                (10, InputCommand::Right, usize::MAX),
+                (11, InputCommand::Right, usize::MAX)
+            ]
+        );
+    }
+
+    #[test]
+    fn release_falls_back_to_held_direction() {
+        let mut auto_shift = AutoShift::new(&HANDLING);
+        auto_shift.press(InputCommand::Left);
+        auto_shift.press(InputCommand::Right);
+        auto_shift.release(InputCommand::Right);
+        assert_eq!(
+            get_shift_frames(&mut auto_shift, 10),
+            [(10, InputCommand::Left, 1)]
+        );
+        auto_shift.release(InputCommand::Left);
+        assert!(get_shift_frames(&mut auto_shift, 20).is_empty());
+    }
+}
@@ -0,0 +1,259 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{mode::GameMode, paths::get_data_directory, stats::GameStats};
+
+const HIGH_SCORES_FILENAME: &str = "highscores.toml";
+pub const MAX_HIGH_SCORES: usize = 10;
+const SECONDS_PER_DAY: u64 = 86_400;
+
+#[derive(Debug)]
+pub enum HighScoreError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
+    NoDataDirectory,
+}
+
+impl fmt::Display for HighScoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HighScoreError::Io(error) => write!(f, "Failed to access high scores: {}", error),
+            HighScoreError::Parse(error) => write!(f, "Failed to parse high scores: {}", error),
+            HighScoreError::Serialize(error) => {
+                write!(f, "Failed to write high scores: {}", error)
+            }
+            HighScoreError::NoDataDirectory => write!(f, "Could not find a data directory"),
+        }
+    }
+}
+
+impl Error for HighScoreError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HighScoreError::Io(error) => Some(error),
+            HighScoreError::Parse(error) => Some(error),
+            HighScoreError::Serialize(error) => Some(error),
+            HighScoreError::NoDataDirectory => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for HighScoreError {
+    fn from(error: std::io::Error) -> Self {
+        HighScoreError::Io(error)
+    }
+}
+
+impl From<toml::de::Error> for HighScoreError {
+    fn from(error: toml::de::Error) -> Self {
+        HighScoreError::Parse(error)
+    }
+}
+
+impl From<toml::ser::Error> for HighScoreError {
+    fn from(error: toml::ser::Error) -> Self {
+        HighScoreError::Serialize(error)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HighScore {
+    pub name: String,
+    pub date: String,
+    pub points: usize,
+    pub lines: usize,
+    pub level: usize,
+    pub time_ms: u64,
+    #[serde(default)]
+    pub splits_ms: Vec<u64>,
+}
+
+impl HighScore {
+    pub fn new(name: &str, stats: &GameStats) -> HighScore {
+        HighScore {
+            name: String::from(name),
+            date: get_current_date(),
+            points: stats.points,
+            lines: stats.lines,
+            level: stats.level,
+            time_ms: stats.elapsed.as_millis() as u64,
+            splits_ms: stats
+                .splits
+                .iter()
+                .map(|split| split.as_millis() as u64)
+                .collect(),
+        }
+    }
+
+    // Whether this score ranks above the other one, equal scores do not
+    fn is_better(&self, other: &HighScore, mode: &GameMode) -> bool {
+        if mode.ranks_by_time() {
+            self.time_ms < other.time_ms
+        } else {
+            self.points > other.points
+        }
+    }
+}
+
+// Scores are kept per mode, keyed by the mode name, best first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HighScoreTable {
+    #[serde(default)]
+    modes: BTreeMap<String, Vec<HighScore>>,
+}
+
+impl HighScoreTable {
+    pub fn get_scores(&self, mode: &GameMode) -> &[HighScore] {
+        match self.modes.get(mode.get_name()) {
+            Some(scores) => scores,
+            None => &[],
+        }
+    }
+
+    // The best score of the mode, compared against while playing
+    pub fn get_personal_best(&self, mode: &GameMode) -> Option<&HighScore> {
+        self.get_scores(mode).first()
+    }
+
+    // Modes with a line goal only rank games that reached it
+    pub fn is_high_score(&self, mode: &GameMode, stats: &GameStats) -> bool {
+        let qualifies = match mode.get_line_goal() {
+            Some(line_goal) => stats.lines >= line_goal,
+            None => stats.points > 0,
+        };
+        let score = HighScore::new("", stats);
+        let scores = self.get_scores(mode);
+        qualifies
+            && (scores.len() < MAX_HIGH_SCORES
+                || scores
+                    .iter()
+                    .any(|existing| score.is_better(existing, mode)))
+    }
+
+    // Returns the rank of the new score, starting at zero
+    pub fn insert(&mut self, mode: &GameMode, score: HighScore) -> usize {
+        let scores = self.modes.entry(String::from(mode.get_name())).or_default();
+        // Equal scores keep the older entry first
+        let rank = scores
+            .iter()
+            .position(|existing| score.is_better(existing, mode))
+            .unwrap_or(scores.len());
+        scores.insert(rank, score);
+        scores.truncate(MAX_HIGH_SCORES);
+        rank
+    }
+
+    // Moves scores saved under an old mode name, like Endless, to the current name
+    fn rename_old_modes(&mut self) {
+        let old_names: Vec<String> = self
+            .modes
+            .keys()
+            .filter(|name| GameMode::from_name(name).is_some_and(|mode| mode.get_name() != *name))
+            .cloned()
+            .collect();
+        for old_name in old_names {
+            let mode = GameMode::from_name(&old_name).unwrap();
+            for score in self.modes.remove(&old_name).unwrap_or_default() {
+                self.insert(&mode, score);
+            }
+        }
+    }
+}
+
+pub fn load_high_scores() -> Result<HighScoreTable, HighScoreError> {
+    let path = get_high_scores_path()?;
+    if !path.exists() {
+        return Ok(HighScoreTable::default());
+    }
+    let contents = fs::read_to_string(&path)?;
+    let mut table: HighScoreTable = toml::from_str(&contents)?;
+    table.rename_old_modes();
+    Ok(table)
+}
+
+pub fn save_high_scores(table: &HighScoreTable) -> Result<(), HighScoreError> {
+    let path = get_high_scores_path()?;
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    fs::write(path, toml::to_string_pretty(table)?)?;
+    Ok(())
+}
+
+fn get_high_scores_path() -> Result<PathBuf, HighScoreError> {
+    match get_data_directory() {
+        Some(directory) => Ok(directory.join(HIGH_SCORES_FILENAME)),
+        None => Err(HighScoreError::NoDataDirectory),
+    }
+}
+
+// Formats today's UTC date as YYYY-MM-DD
+fn get_current_date() -> String {
+    let seconds = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(time) => time.as_secs(),
+        Err(_) => 0,
+    };
+    let (year, month, day) = get_civil_date(seconds / SECONDS_PER_DAY);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// Converts days since 1970-01-01 to a calendar date, using Howard Hinnant's algorithm
+fn get_civil_date(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD_HIGH_SCORES: &str = r#"
+[[modes.Endless]]
+name = "old"
+date = "2024-01-01"
+points = 500
+lines = 12
+level = 2
+time_ms = 60000
+
+[[modes.Marathon]]
+name = "new"
+date = "2024-02-01"
+points = 800
+lines = 20
+level = 3
+time_ms = 90000
+"#;
+
+    #[test]
+    fn endless_scores_move_to_marathon() {
+        let mut table: HighScoreTable = toml::from_str(OLD_HIGH_SCORES).unwrap();
+        table.rename_old_modes();
+        let names: Vec<&str> = table
+            .get_scores(&GameMode::Marathon)
+            .iter()
+            .map(|score| score.name.as_str())
+            .collect();
+        assert_eq!(names, ["new", "old"]);
+        assert!(!table.modes.contains_key("Endless"));
+    }
+}
@@ -1,64 +1,245 @@
 use std::io;
 
-use crossterm::event::{read, Event, KeyCode};
+use crossterm::event::{read, Event, KeyCode, KeyEventKind};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     board::{
         clear_previous_tetromino_from_board, copy_tetromino_positions, get_tetromino_positions,
-        is_tetromino_position_overlapping_with_block, is_tetromino_positions_out_of_bounds,
-        move_tetromino_back_in_bounds, move_tetromino_sideways, Coordinate, MoveDirection,
-        BOARD_HEIGHT, BOARD_WIDTH,
+        is_tetromino_positions_out_of_bounds, kick_tetromino, move_tetromino_back_in_bounds,
+        move_tetromino_sideways, Coordinate, MoveDirection,
     },
     tetromino::{
         is_left_of_tetromino_empty, is_top_of_tetromino_empty, move_tetromino_left_by_one,
-        move_tetromino_up_by_one, rotate_tetromino, TETROMINO_SIZE,
+        move_tetromino_up_by_one, rotate_tetromino, rotate_tetromino_clockwise, TETROMINO_SIZE,
     },
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum InputCommand {
     Left,
     Right,
     Down,
-    Rotate,
+    RotateCw,
+    RotateCcw,
+    Rotate180,
+    HardDrop,
+    Suspend,
+    Mute,
     Exit,
     None,
 }
 
-pub fn read_user_input() -> io::Result<InputCommand> {
-    let read_result = read();
-    if let Ok(event) = read_result {
-        match event {
-            Event::Key(key) => match key.code {
-                KeyCode::Down => return Ok(InputCommand::Down),
-                KeyCode::Left => return Ok(InputCommand::Left),
-                KeyCode::Right => return Ok(InputCommand::Right),
-                KeyCode::Char(character) => {
-                    if character == 'r' {
-                        return Ok(InputCommand::Rotate);
-                    }
-                    return Ok(InputCommand::None);
-                }
-                KeyCode::Esc => return Ok(InputCommand::Exit),
-                _ => return Ok(InputCommand::None),
-            },
-            _ => return Ok(InputCommand::None),
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyAction {
+    Press,
+    Repeat,
+    Release,
+}
+
+pub const BINDABLE_COMMANDS: [InputCommand; 10] = [
+    InputCommand::Left,
+    InputCommand::Right,
+    InputCommand::Down,
+    InputCommand::RotateCw,
+    InputCommand::RotateCcw,
+    InputCommand::Rotate180,
+    InputCommand::HardDrop,
+    InputCommand::Suspend,
+    InputCommand::Mute,
+    InputCommand::Exit,
+];
+
+const NAMED_KEYS: [(KeyCode, &str); 15] = [
+    (KeyCode::Left, "Left"),
+    (KeyCode::Right, "Right"),
+    (KeyCode::Up, "Up"),
+    (KeyCode::Down, "Down"),
+    (KeyCode::Esc, "Esc"),
+    (KeyCode::Enter, "Enter"),
+    (KeyCode::Char(' '), "Space"),
+    (KeyCode::Tab, "Tab"),
+    (KeyCode::Backspace, "Backspace"),
+    (KeyCode::Delete, "Delete"),
+    (KeyCode::Insert, "Insert"),
+    (KeyCode::Home, "Home"),
+    (KeyCode::End, "End"),
+    (KeyCode::PageUp, "PageUp"),
+    (KeyCode::PageDown, "PageDown"),
+];
+
+const FUNCTION_KEY_COUNT: u8 = 12;
+
+impl InputCommand {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            InputCommand::Left => "Left",
+            InputCommand::Right => "Right",
+            InputCommand::Down => "Down",
+            InputCommand::RotateCw => "Rotate CW",
+            InputCommand::RotateCcw => "Rotate CCW",
+            InputCommand::Rotate180 => "Rotate 180",
+            InputCommand::HardDrop => "Hard drop",
+            InputCommand::Suspend => "Suspend",
+            InputCommand::Mute => "Mute",
+            InputCommand::Exit => "Exit",
+            InputCommand::None => "None",
+        }
+    }
+
+    // The key of this command in the [keys] section of the config file
+    pub fn get_config_name(&self) -> &'static str {
+        match self {
+            InputCommand::Left => "left",
+            InputCommand::Right => "right",
+            InputCommand::Down => "down",
+            InputCommand::RotateCw => "rotate_cw",
+            InputCommand::RotateCcw => "rotate_ccw",
+            InputCommand::Rotate180 => "rotate_180",
+            InputCommand::HardDrop => "hard_drop",
+            InputCommand::Suspend => "suspend",
+            InputCommand::Mute => "mute",
+            InputCommand::Exit => "exit",
+            InputCommand::None => "none",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+    pub left: Vec<String>,
+    pub right: Vec<String>,
+    pub down: Vec<String>,
+    pub rotate_cw: Vec<String>,
+    // Older config files only had a single counter-clockwise rotate binding
+    #[serde(alias = "rotate")]
+    pub rotate_ccw: Vec<String>,
+    pub rotate_180: Vec<String>,
+    pub hard_drop: Vec<String>,
+    pub suspend: Vec<String>,
+    pub mute: Vec<String>,
+    pub exit: Vec<String>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            left: vec![String::from("Left")],
+            right: vec![String::from("Right")],
+            down: vec![String::from("Down")],
+            rotate_cw: vec![String::from("Up"), String::from("x")],
+            rotate_ccw: vec![String::from("r"), String::from("z")],
+            rotate_180: vec![String::from("a")],
+            hard_drop: vec![String::from("Space")],
+            suspend: vec![String::from("s")],
+            mute: vec![String::from("m")],
+            exit: vec![String::from("Esc")],
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn get_keys(&self, command: &InputCommand) -> &[String] {
+        match command {
+            InputCommand::Left => &self.left,
+            InputCommand::Right => &self.right,
+            InputCommand::Down => &self.down,
+            InputCommand::RotateCw => &self.rotate_cw,
+            InputCommand::RotateCcw => &self.rotate_ccw,
+            InputCommand::Rotate180 => &self.rotate_180,
+            InputCommand::HardDrop => &self.hard_drop,
+            InputCommand::Suspend => &self.suspend,
+            InputCommand::Mute => &self.mute,
+            InputCommand::Exit => &self.exit,
+            InputCommand::None => &[],
+        }
+    }
+
+    pub fn set_keys(&mut self, command: &InputCommand, keys: Vec<String>) {
+        // A key can only trigger one command, so take it away from the others
+        for other in BINDABLE_COMMANDS {
+            if let Some(other_keys) = self.get_keys_mut(&other) {
+                other_keys.retain(|key| !keys.contains(key));
+            }
+        }
+        if let Some(command_keys) = self.get_keys_mut(command) {
+            *command_keys = keys;
         }
-    } else if let Err(error) = read_result {
-        eprint!("Error when reading result: {}\r\n", error);
-        if let Err(raw_mode_error) = crossterm::terminal::disable_raw_mode() {
-            print!("Failed to disable raw_mode: {}\r\n", raw_mode_error);
+    }
+
+    pub fn get_command(&self, key_name: &str) -> InputCommand {
+        for command in BINDABLE_COMMANDS {
+            if self.get_keys(&command).iter().any(|key| key == key_name) {
+                return command;
+            }
+        }
+        InputCommand::None
+    }
+
+    fn get_keys_mut(&mut self, command: &InputCommand) -> Option<&mut Vec<String>> {
+        match command {
+            InputCommand::Left => Some(&mut self.left),
+            InputCommand::Right => Some(&mut self.right),
+            InputCommand::Down => Some(&mut self.down),
+            InputCommand::RotateCw => Some(&mut self.rotate_cw),
+            InputCommand::RotateCcw => Some(&mut self.rotate_ccw),
+            InputCommand::Rotate180 => Some(&mut self.rotate_180),
+            InputCommand::HardDrop => Some(&mut self.hard_drop),
+            InputCommand::Suspend => Some(&mut self.suspend),
+            InputCommand::Mute => Some(&mut self.mute),
+            InputCommand::Exit => Some(&mut self.exit),
+            InputCommand::None => None,
+        }
+    }
+}
+
+pub fn get_key_name(code: &KeyCode) -> Option<String> {
+    if let Some((_, name)) = NAMED_KEYS.iter().find(|(key, _)| key == code) {
+        return Some(String::from(*name));
+    }
+    match code {
+        KeyCode::Char(character) => Some(character.to_string()),
+        KeyCode::F(number) => Some(format!("F{}", number)),
+        _ => None,
+    }
+}
+
+pub fn is_valid_key_name(name: &str) -> bool {
+    if name.chars().count() == 1 {
+        return true;
+    }
+    if NAMED_KEYS.iter().any(|(_, key_name)| *key_name == name) {
+        return true;
+    }
+    match name.strip_prefix('F').map(|number| number.parse::<u8>()) {
+        Some(Ok(number)) => (1..=FUNCTION_KEY_COUNT).contains(&number),
+        _ => false,
+    }
+}
+
+pub fn read_user_input(key_bindings: &KeyBindings) -> io::Result<(InputCommand, KeyAction)> {
+    match read()? {
+        Event::Key(key) => {
+            let action = match key.kind {
+                KeyEventKind::Press => KeyAction::Press,
+                KeyEventKind::Repeat => KeyAction::Repeat,
+                KeyEventKind::Release => KeyAction::Release,
+            };
+            match get_key_name(&key.code) {
+                Some(key_name) => Ok((key_bindings.get_command(&key_name), action)),
+                None => Ok((InputCommand::None, action)),
+            }
         }
-        std::process::exit(1);
+        _ => Ok((InputCommand::None, KeyAction::Press)),
     }
-    Ok(InputCommand::None)
 }
 
 pub fn commit_action(
     input: &InputCommand,
     tetromino_positions: &mut [Coordinate; TETROMINO_SIZE],
     tetromino: &mut [[char; TETROMINO_SIZE]; TETROMINO_SIZE],
-    board: &mut [[char; BOARD_WIDTH]; BOARD_HEIGHT],
+    board: &mut [Vec<char>],
 ) -> bool {
     match input {
         InputCommand::Left => {
@@ -73,13 +254,22 @@ pub fn commit_action(
                 clear_previous_tetromino_from_board(&previous_tetromino_positions, board);
             }
         }
-        InputCommand::Exit => {
+        InputCommand::Exit | InputCommand::Suspend | InputCommand::Mute => {
             return false;
         }
-        InputCommand::Down => {}
-        InputCommand::Rotate => {
-            let previous_tetromino_positions = copy_tetromino_positions(&tetromino_positions);
-            rotate_tetromino(tetromino);
+        // Dropping is done by the engine, which knows when a tetromino locks
+        InputCommand::Down | InputCommand::HardDrop => {}
+        InputCommand::RotateCw | InputCommand::RotateCcw | InputCommand::Rotate180 => {
+            let previous_tetromino = *tetromino;
+            let previous_tetromino_positions = copy_tetromino_positions(tetromino_positions);
+            match input {
+                InputCommand::RotateCw => rotate_tetromino_clockwise(tetromino),
+                InputCommand::Rotate180 => {
+                    rotate_tetromino(tetromino);
+                    rotate_tetromino(tetromino);
+                }
+                _ => rotate_tetromino(tetromino),
+            }
 
             // Prevents tetrominos moving when rotating
             // Move tetromino back to up position after rotation
@@ -93,19 +283,18 @@ pub fn commit_action(
             clear_previous_tetromino_from_board(&previous_tetromino_positions, board);
             let mut updated_tetromino_positions =
                 get_tetromino_positions(tetromino, tetromino_positions);
-            let out_of_bounds = is_tetromino_positions_out_of_bounds(&updated_tetromino_positions);
+            let out_of_bounds =
+                is_tetromino_positions_out_of_bounds(&updated_tetromino_positions, board);
             if out_of_bounds.get_difference() > 0 {
                 move_tetromino_back_in_bounds(&out_of_bounds, &mut updated_tetromino_positions);
             }
-            for i in 0..TETROMINO_SIZE {
-                tetromino_positions[i] = updated_tetromino_positions[i];
-            }
 
-            // Check if updated_tetromino_positions is overlapping with block
-            if is_tetromino_position_overlapping_with_block(tetromino_positions, board) {
-                for i in 0..TETROMINO_SIZE {
-                    tetromino_positions[i] = previous_tetromino_positions[i];
+            // Keep the old rotation if no kick makes the rotated tetromino fit
+            match kick_tetromino(&updated_tetromino_positions, board) {
+                Some(kicked_tetromino_positions) => {
+                    *tetromino_positions = kicked_tetromino_positions;
                 }
+                None => *tetromino = previous_tetromino,
             }
         }
         InputCommand::None => {}
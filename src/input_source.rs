@@ -0,0 +1,133 @@
+#[cfg(test)]
+use std::collections::VecDeque;
+use std::{io, time::Duration};
+
+use crossterm::event::poll;
+
+use crate::input::{read_user_input, InputCommand, KeyAction, KeyBindings};
+
+// An input and the engine frame it applies to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedInput {
+    pub frame: u64,
+    pub command: InputCommand,
+    pub action: KeyAction,
+}
+
+pub trait InputSource {
+    // Waits at most `timeout` for the next input of `frame` and returns None if nothing arrived
+    fn next_input(&mut self, frame: u64, timeout: Duration) -> io::Result<Option<TimedInput>>;
+}
+
+pub struct TerminalInput<'a> {
+    key_bindings: &'a KeyBindings,
+}
+
+impl<'a> TerminalInput<'a> {
+    pub fn new(key_bindings: &'a KeyBindings) -> TerminalInput<'a> {
+        TerminalInput { key_bindings }
+    }
+}
+
+impl InputSource for TerminalInput<'_> {
+    fn next_input(&mut self, frame: u64, timeout: Duration) -> io::Result<Option<TimedInput>> {
+        if !poll(timeout)? {
+            return Ok(None);
+        }
+        let (command, action) = read_user_input(self.key_bindings)?;
+        Ok(Some(TimedInput {
+            frame,
+            command,
+            action,
+        }))
+    }
+}
+
+// Plays recorded inputs back in the frames they were recorded in, without waiting
+pub struct ReplayInput<'a> {
+    inputs: &'a [TimedInput],
+    next_input: usize,
+}
+
+impl<'a> ReplayInput<'a> {
+    pub fn new(inputs: &'a [TimedInput]) -> ReplayInput<'a> {
+        ReplayInput {
+            inputs,
+            next_input: 0,
+        }
+    }
+}
+
+impl InputSource for ReplayInput<'_> {
+    fn next_input(&mut self, frame: u64, _timeout: Duration) -> io::Result<Option<TimedInput>> {
+        match self.inputs.get(self.next_input) {
+            Some(input) if input.frame == frame => {
+                self.next_input += 1;
+                Ok(Some(*input))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+// Hands out its inputs in order once their frame is reached, then asks the game to exit
+#[cfg(test)]
+pub struct ScriptedInput {
+    inputs: VecDeque<TimedInput>,
+}
+
+#[cfg(test)]
+impl ScriptedInput {
+    pub fn new(inputs: Vec<TimedInput>) -> ScriptedInput {
+        ScriptedInput {
+            inputs: inputs.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl InputSource for ScriptedInput {
+    fn next_input(&mut self, frame: u64, _timeout: Duration) -> io::Result<Option<TimedInput>> {
+        match self.inputs.front() {
+            Some(input) if input.frame > frame => Ok(None),
+            Some(_) => Ok(self.inputs.pop_front()),
+            None => Ok(Some(TimedInput {
+                frame,
+                command: InputCommand::Exit,
+                action: KeyAction::Press,
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_inputs_wait_for_their_frame() {
+        let inputs = [
+            TimedInput {
+                frame: 0,
+                command: InputCommand::Left,
+                action: KeyAction::Press,
+            },
+            TimedInput {
+                frame: 2,
+                command: InputCommand::HardDrop,
+                action: KeyAction::Press,
+            },
+        ];
+        let mut input_source = ReplayInput::new(&inputs);
+        let mut received = Vec::new();
+        for frame in 0..4 {
+            while let Some(input) = input_source.next_input(frame, Duration::ZERO).unwrap() {
+                received.push((frame, input.command));
+            }
+        }
+        assert_eq!(
+            received,
+            [(0, InputCommand::Left), (2, InputCommand::HardDrop)]
+        );
+    }
+}
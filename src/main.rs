@@ -1,27 +1,242 @@
+mod animation;
 mod audio;
+mod audio_backend;
+mod bench;
 mod board;
+mod cli;
+mod config;
+mod engine;
+mod events;
 mod game;
+mod handling;
+mod highscores;
 mod input;
+mod input_source;
+mod menu;
+mod mode;
+mod paths;
+mod render;
+mod replay;
+mod savegame;
+mod scoring;
+mod settings;
+mod sfx;
+mod stats;
+mod synth;
 mod tetromino;
+mod theme;
 
-use audio::play_audio;
-use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+use std::path::{Path, PathBuf};
 
-use game::start_game;
+use audio::{get_playlist, get_selected_track, play_audio};
+use audio_backend::{AudioBackend, NullAudio};
+use bench::run_bench;
+use board::BoardSize;
+use clap::Parser;
+use cli::{Cli, Command};
+use config::{get_default_config_path, load_config};
+use crossterm::{
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, SetSize},
+};
+
+use game::create_ruleset;
+use menu::run_main_menu;
+use render::{render_music, render_replay_effects};
+use replay::{load_replay, play_replay, verify_replay};
+use savegame::take_saved_game;
+use settings::Settings;
+
+// Rows below the board for the mode and status lines and some room to spare
+const TERMINAL_EXTRA_ROWS: usize = 6;
 
 fn main() -> std::io::Result<()> {
+    let cli = Cli::parse();
+    match &cli.command {
+        Some(Command::Replay { file }) => return run_replay_file(&cli, file),
+        Some(Command::Verify { files }) => {
+            let all_verified = verify_replay_files(files);
+            std::process::exit(if all_verified { 0 } else { 1 });
+        }
+        Some(Command::Render {
+            output,
+            replay,
+            seconds,
+        }) => {
+            render_file(&cli, output, replay.as_deref(), *seconds);
+            return Ok(());
+        }
+        Some(Command::Bench { games }) => {
+            let mut settings = Settings::default();
+            apply_options(&mut settings, &cli);
+            // Benchmarks are reproducible unless a seed is given
+            settings.seed = Some(cli.seed.unwrap_or(0));
+            run_bench(&create_ruleset(&settings, false), *games);
+            return Ok(());
+        }
+        None => {
+            if let Some(file) = &cli.replay {
+                return run_replay_file(&cli, file);
+            }
+        }
+    }
+
+    let saved_game = if cli.resume {
+        match take_saved_game() {
+            Ok(saved_game) => Some(saved_game),
+            Err(error) => {
+                eprintln!("Could not resume game: {}", error);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    let (settings, status) = load_settings(&cli);
+
     enable_raw_mode()?;
+    set_terminal_size(&settings, &settings.board_size)?;
 
-    match play_audio() {
-        Ok(audio) => start_game(Some(&audio))?,
+    if cli.no_audio {
+        run_main_menu(settings, &NullAudio, saved_game, status)?;
+    } else {
+        let playlist = get_playlist(settings.music_file.as_deref(), &settings.playlist_files);
+        let track = &playlist[get_selected_track(&playlist, settings.track.as_deref())];
+        // Without an output device the menu shows why, and the device is retried
+        let audio = play_audio(track);
+        audio.set_volumes(settings.music_volume, settings.effects_volume);
+        audio.set_music_playing(settings.music);
+        run_main_menu(settings, &audio, saved_game, status)?;
+    }
+
+    disable_raw_mode()?;
+    Ok(())
+}
 
+// Also returns a warning when the defaults are used. A config file that failed to load
+// is never saved over, so the settings get no config path
+fn load_settings(cli: &Cli) -> (Settings, String) {
+    let config_path = match &cli.config {
+        Some(path) => Some(path.clone()),
+        None => get_default_config_path().ok(),
+    };
+    let (config, config_path, warning) = match config_path {
+        Some(path) => match load_config(&path) {
+            Ok(config) => (config, Some(path), String::new()),
+            Err(error) => (
+                Default::default(),
+                None,
+                // Parse errors span several lines, and the menu is drawn in raw mode
+                format!("Could not load config, using defaults: {}", error).replace('\n', "\r\n"),
+            ),
+        },
+        None => (
+            Default::default(),
+            None,
+            String::from("Could not find a config directory, using defaults"),
+        ),
+    };
+    let mut settings = Settings::from_config(config, config_path);
+    apply_options(&mut settings, cli);
+    (settings, warning)
+}
+
+fn apply_options(settings: &mut Settings, cli: &Cli) {
+    if let Some(mode) = cli.mode {
+        settings.mode = mode;
+    }
+    if let Some(level) = cli.level {
+        settings.start_level = level;
+    }
+    if cli.endless {
+        settings.endless = true;
+    }
+    if let Some(width) = cli.width {
+        settings.board_size.width = width;
+    }
+    if let Some(height) = cli.height {
+        settings.board_size.height = height;
+    }
+    if let Some(music) = &cli.music {
+        // The music file is first in the playlist, so it plays instead of the selected track
+        settings.music_file = Some(music.clone());
+        settings.track = None;
+    }
+    settings.seed = cli.seed;
+}
+
+// Grows the configured size when the board would not fit
+fn set_terminal_size(settings: &Settings, board_size: &BoardSize) -> std::io::Result<()> {
+    // Every cell is drawn two columns wide, with a wall on each side
+    let width = (((board_size.width + 2) * 2) as u16).max(settings.terminal_width);
+    let height = ((board_size.height + TERMINAL_EXTRA_ROWS) as u16).max(settings.terminal_height);
+    execute!(std::io::stdout(), SetSize(width, height))
+}
+
+fn run_replay_file(cli: &Cli, path: &Path) -> std::io::Result<()> {
+    let replay = match load_replay(path) {
+        Ok(replay) => replay,
         Err(error) => {
-            eprint!("Could not play audio: {}\r\n", error);
+            eprintln!("Could not load replay: {}", error);
+            std::process::exit(1);
+        }
+    };
+    enable_raw_mode()?;
+    // Nothing is saved while watching, the defaults will do when the config is broken
+    let (settings, _) = load_settings(cli);
+    set_terminal_size(&settings, &replay.ruleset.board_size)?;
+    let result = play_replay(&replay);
+    disable_raw_mode()?;
+    result
+}
 
-            start_game(None)?;
+// Renders the selected track, or the sound effects of a replay when one is given
+fn render_file(cli: &Cli, output: &Path, replay_path: Option<&Path>, seconds: f64) {
+    let (settings, warning) = load_settings(cli);
+    if !warning.is_empty() {
+        eprintln!("{}", warning);
+    }
+    let rendered = match replay_path {
+        Some(replay_path) => match load_replay(replay_path) {
+            Ok(replay) => render_replay_effects(&replay, &settings, output),
+            Err(error) => {
+                eprintln!("Could not load replay: {}", error);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            let playlist = get_playlist(settings.music_file.as_deref(), &settings.playlist_files);
+            let track = &playlist[get_selected_track(&playlist, settings.track.as_deref())];
+            render_music(track, &settings, seconds, output)
+        }
+    };
+    match rendered {
+        Ok(()) => println!("Rendered {}", output.display()),
+        Err(error) => {
+            eprintln!("Could not render audio: {}", error);
+            std::process::exit(1);
         }
     }
+}
 
-    disable_raw_mode()?;
-    Ok(())
+fn verify_replay_files(paths: &[PathBuf]) -> bool {
+    let mut all_verified = true;
+    for path in paths {
+        let verified = load_replay(path).and_then(|replay| verify_replay(&replay));
+        match verified {
+            Ok(mismatches) if mismatches.is_empty() => println!("OK {}", path.display()),
+            Ok(mismatches) => {
+                println!("MISMATCH {}", path.display());
+                for mismatch in mismatches {
+                    println!("    {}", mismatch);
+                }
+                all_verified = false;
+            }
+            Err(error) => {
+                println!("ERROR {}: {}", path.display(), error);
+                all_verified = false;
+            }
+        }
+    }
+    all_verified
 }
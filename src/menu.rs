@@ -0,0 +1,713 @@
+use std::{
+    io::{self, stdout},
+    time::Duration,
+};
+
+use crossterm::{
+    cursor::MoveTo,
+    event::{read, Event, KeyCode, KeyEventKind},
+    execute,
+    terminal::{Clear, ClearType},
+};
+
+use crate::{
+    audio::{get_playlist, get_selected_track, Track},
+    audio_backend::AudioBackend,
+    config::{save_config, MAX_VOLUME},
+    engine::{GameEnd, MAX_LEVEL},
+    game::{resume_game, start_game, FinishedGame},
+    handling::{MAX_ARR_MILLISECONDS, MAX_DAS_MILLISECONDS},
+    highscores::{load_high_scores, save_high_scores, HighScore, HighScoreTable},
+    input::{get_key_name, KeyBindings, BINDABLE_COMMANDS},
+    mode::GameMode,
+    replay::{get_new_replay_path, save_replay},
+    savegame::SavedGame,
+    settings::Settings,
+    stats::{format_duration, format_precise_duration, GameStats, CLEAR_TYPE_NAMES},
+};
+
+const TITLE: [&str; 5] = [
+    "#####  #####  #####  ####   #####  #####",
+    "  #    #        #    #   #    #    #    ",
+    "  #    ####     #    ####     #    #####",
+    "  #    #        #    #  #     #        #",
+    "  #    #####    #    #   #  #####  #####",
+];
+
+const MAX_NAME_LENGTH: usize = 12;
+const DAS_STEP_MILLISECONDS: u64 = 10;
+const ARR_STEP_MILLISECONDS: u64 = 5;
+const VOLUME_STEP: u8 = 10;
+
+pub enum MenuInput {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Back,
+    None,
+}
+
+#[derive(Clone, Copy)]
+enum MainMenuItem {
+    Start,
+    Mode,
+    StartLevel,
+    Endless,
+    Options,
+    HighScores,
+    Quit,
+}
+
+// Start level and endless are left out for modes without them
+const MAIN_MENU_ITEMS: [MainMenuItem; 7] = [
+    MainMenuItem::Start,
+    MainMenuItem::Mode,
+    MainMenuItem::StartLevel,
+    MainMenuItem::Endless,
+    MainMenuItem::Options,
+    MainMenuItem::HighScores,
+    MainMenuItem::Quit,
+];
+
+#[derive(Clone, Copy)]
+enum OptionsMenuItem {
+    Music,
+    MusicVolume,
+    EffectsVolume,
+    Track,
+    Animations,
+    Das,
+    Arr,
+    KeyBindings,
+    Back,
+}
+
+const OPTIONS_MENU_ITEMS: [OptionsMenuItem; 9] = [
+    OptionsMenuItem::Music,
+    OptionsMenuItem::MusicVolume,
+    OptionsMenuItem::EffectsVolume,
+    OptionsMenuItem::Track,
+    OptionsMenuItem::Animations,
+    OptionsMenuItem::Das,
+    OptionsMenuItem::Arr,
+    OptionsMenuItem::KeyBindings,
+    OptionsMenuItem::Back,
+];
+
+#[derive(Clone, Copy)]
+enum ResultsMenuItem {
+    Retry,
+    Menu,
+    SaveReplay,
+}
+
+const RESULTS_MENU_ITEMS: [ResultsMenuItem; 3] = [
+    ResultsMenuItem::Retry,
+    ResultsMenuItem::Menu,
+    ResultsMenuItem::SaveReplay,
+];
+
+pub fn read_menu_input() -> io::Result<MenuInput> {
+    match read()? {
+        Event::Key(key) if key.kind != KeyEventKind::Release => match key.code {
+            KeyCode::Up => Ok(MenuInput::Up),
+            KeyCode::Down => Ok(MenuInput::Down),
+            KeyCode::Left => Ok(MenuInput::Left),
+            KeyCode::Right => Ok(MenuInput::Right),
+            KeyCode::Enter | KeyCode::Char(' ') => Ok(MenuInput::Select),
+            KeyCode::Esc | KeyCode::Char('q') => Ok(MenuInput::Back),
+            _ => Ok(MenuInput::None),
+        },
+        _ => Ok(MenuInput::None),
+    }
+}
+
+pub fn wait_for_confirmation() -> io::Result<()> {
+    loop {
+        if let MenuInput::Select | MenuInput::Back = read_menu_input()? {
+            return Ok(());
+        }
+    }
+}
+
+pub fn run_main_menu(
+    mut settings: Settings,
+    audio: &dyn AudioBackend,
+    saved_game: Option<SavedGame>,
+    mut status: String,
+) -> io::Result<()> {
+    let mut high_scores = match load_high_scores() {
+        Ok(high_scores) => high_scores,
+        Err(error) => {
+            if !status.is_empty() {
+                status.push_str("\r\n");
+            }
+            status.push_str(&error.to_string());
+            HighScoreTable::default()
+        }
+    };
+    let mut player_name = String::new();
+    if let Some(saved_game) = saved_game {
+        let game_status = play_games(
+            &settings,
+            audio,
+            &mut high_scores,
+            &mut player_name,
+            Some(saved_game),
+        )?;
+        // Warnings from loading stay visible unless the game has something to say
+        if !game_status.is_empty() {
+            status = game_status;
+        }
+    }
+    let mut selected = 0;
+    loop {
+        let items = get_main_menu_items(&settings.mode);
+        let labels: Vec<String> = items
+            .iter()
+            .map(|item| main_menu_label(item, &settings))
+            .collect();
+        audio.check_output();
+        let unavailable = audio.is_enabled() && !audio.is_available();
+        let footer = match (unavailable, audio.get_error()) {
+            (false, _) => status.clone(),
+            (true, Some(error)) => format!("Audio unavailable: {}\r\n{}", error, status),
+            (true, None) => format!("Audio unavailable\r\n{}", status),
+        };
+        draw_menu(&TITLE, &labels, selected, &footer)?;
+
+        match read_menu_input()? {
+            MenuInput::Up => selected = previous_index(selected, items.len()),
+            MenuInput::Down => selected = next_index(selected, items.len()),
+            MenuInput::Left => match items[selected] {
+                MainMenuItem::Mode => settings.mode = settings.mode.previous(),
+                MainMenuItem::StartLevel => {
+                    settings.start_level = settings.start_level.saturating_sub(1).max(1);
+                }
+                MainMenuItem::Endless => settings.endless = !settings.endless,
+                _ => {}
+            },
+            MenuInput::Right => match items[selected] {
+                MainMenuItem::Mode => settings.mode = settings.mode.next(),
+                MainMenuItem::StartLevel => {
+                    settings.start_level = (settings.start_level + 1).min(MAX_LEVEL);
+                }
+                MainMenuItem::Endless => settings.endless = !settings.endless,
+                _ => {}
+            },
+            MenuInput::Select => match items[selected] {
+                MainMenuItem::Start => {
+                    status =
+                        play_games(&settings, audio, &mut high_scores, &mut player_name, None)?;
+                }
+                MainMenuItem::Mode => settings.mode = settings.mode.next(),
+                MainMenuItem::StartLevel => {
+                    settings.start_level = settings.start_level % MAX_LEVEL + 1;
+                }
+                MainMenuItem::Endless => settings.endless = !settings.endless,
+                MainMenuItem::Options => run_options_menu(&mut settings, audio)?,
+                MainMenuItem::HighScores => show_high_scores(&high_scores, settings.mode)?,
+                MainMenuItem::Quit => break,
+            },
+            MenuInput::Back => break,
+            MenuInput::None => {}
+        }
+    }
+    execute!(stdout(), MoveTo(0, 0), Clear(ClearType::All))?;
+    Ok(())
+}
+
+// Plays until the player leaves the results screen, starting with the saved game if given,
+// returns a status for the main menu
+fn play_games(
+    settings: &Settings,
+    audio: &dyn AudioBackend,
+    high_scores: &mut HighScoreTable,
+    player_name: &mut String,
+    mut saved_game: Option<SavedGame>,
+) -> io::Result<String> {
+    let mut game_settings = settings.clone();
+    loop {
+        let finished_game = match saved_game.take() {
+            Some(saved_game) => {
+                let personal_best = high_scores.get_personal_best(&saved_game.replay.ruleset.mode);
+                resume_game(settings, audio, saved_game, personal_best)?
+            }
+            None => {
+                let personal_best = high_scores.get_personal_best(&game_settings.mode);
+                start_game(&game_settings, audio, personal_best)?
+            }
+        };
+        if let GameEnd::Suspended = finished_game.game_end {
+            return Ok(String::from("Game suspended, continue it with --resume"));
+        }
+        let results_status = record_high_score(
+            high_scores,
+            player_name,
+            &finished_game.replay.ruleset.mode,
+            &finished_game.stats,
+        )?;
+        if let GameEnd::Quit = finished_game.game_end {
+            return Ok(String::new());
+        }
+        match show_results(&finished_game, results_status)? {
+            ResultsMenuItem::Retry => {
+                // A resumed game may have been started with other rules than the menu's
+                let ruleset = &finished_game.replay.ruleset;
+                game_settings.mode = ruleset.mode;
+                game_settings.start_level = ruleset.start_level;
+                game_settings.endless = ruleset.endless;
+                game_settings.board_size = ruleset.board_size;
+            }
+            _ => return Ok(String::new()),
+        }
+    }
+}
+
+fn run_options_menu(settings: &mut Settings, audio: &dyn AudioBackend) -> io::Result<()> {
+    let playlist = get_playlist(settings.music_file.as_deref(), &settings.playlist_files);
+    let mut track_index = get_selected_track(&playlist, settings.track.as_deref());
+    let mut selected = 0;
+    let mut status = String::new();
+    loop {
+        let labels: Vec<String> = OPTIONS_MENU_ITEMS
+            .iter()
+            .map(|item| options_menu_label(item, settings, &playlist[track_index]))
+            .collect();
+        draw_menu(&["OPTIONS"], &labels, selected, &status)?;
+
+        match (read_menu_input()?, OPTIONS_MENU_ITEMS[selected]) {
+            (MenuInput::Up, _) => selected = previous_index(selected, OPTIONS_MENU_ITEMS.len()),
+            (MenuInput::Down, _) => selected = next_index(selected, OPTIONS_MENU_ITEMS.len()),
+            (MenuInput::Left, OptionsMenuItem::MusicVolume) => {
+                settings.music_volume = settings.music_volume.saturating_sub(VOLUME_STEP);
+                apply_volume_settings(settings, audio);
+                status = save_settings(settings);
+            }
+            (MenuInput::Right, OptionsMenuItem::MusicVolume) => {
+                settings.music_volume = (settings.music_volume + VOLUME_STEP).min(MAX_VOLUME);
+                apply_volume_settings(settings, audio);
+                status = save_settings(settings);
+            }
+            (MenuInput::Left, OptionsMenuItem::EffectsVolume) => {
+                settings.effects_volume = settings.effects_volume.saturating_sub(VOLUME_STEP);
+                apply_volume_settings(settings, audio);
+                status = save_settings(settings);
+            }
+            (MenuInput::Right, OptionsMenuItem::EffectsVolume) => {
+                settings.effects_volume = (settings.effects_volume + VOLUME_STEP).min(MAX_VOLUME);
+                apply_volume_settings(settings, audio);
+                status = save_settings(settings);
+            }
+            (MenuInput::Left, OptionsMenuItem::Track) => {
+                track_index = previous_index(track_index, playlist.len());
+                status = select_track(settings, audio, &playlist[track_index]);
+            }
+            (MenuInput::Right | MenuInput::Select, OptionsMenuItem::Track) => {
+                track_index = next_index(track_index, playlist.len());
+                status = select_track(settings, audio, &playlist[track_index]);
+            }
+            (MenuInput::Left, OptionsMenuItem::Das) => {
+                settings.handling.das_ms = settings
+                    .handling
+                    .das_ms
+                    .saturating_sub(DAS_STEP_MILLISECONDS);
+                status = save_settings(settings);
+            }
+            (MenuInput::Right, OptionsMenuItem::Das) => {
+                settings.handling.das_ms =
+                    (settings.handling.das_ms + DAS_STEP_MILLISECONDS).min(MAX_DAS_MILLISECONDS);
+                status = save_settings(settings);
+            }
+            (MenuInput::Left, OptionsMenuItem::Arr) => {
+                settings.handling.arr_ms = settings
+                    .handling
+                    .arr_ms
+                    .saturating_sub(ARR_STEP_MILLISECONDS);
+                status = save_settings(settings);
+            }
+            (MenuInput::Right, OptionsMenuItem::Arr) => {
+                settings.handling.arr_ms =
+                    (settings.handling.arr_ms + ARR_STEP_MILLISECONDS).min(MAX_ARR_MILLISECONDS);
+                status = save_settings(settings);
+            }
+            (MenuInput::Left | MenuInput::Right | MenuInput::Select, _) => {
+                match OPTIONS_MENU_ITEMS[selected] {
+                    OptionsMenuItem::Music => {
+                        settings.music = !settings.music;
+                        apply_music_setting(settings, audio);
+                        status = save_settings(settings);
+                    }
+                    OptionsMenuItem::Animations => {
+                        settings.animations = !settings.animations;
+                        status = save_settings(settings);
+                    }
+                    OptionsMenuItem::KeyBindings => run_key_bindings_menu(settings)?,
+                    OptionsMenuItem::Back => return Ok(()),
+                    OptionsMenuItem::MusicVolume
+                    | OptionsMenuItem::EffectsVolume
+                    | OptionsMenuItem::Track
+                    | OptionsMenuItem::Das
+                    | OptionsMenuItem::Arr => {}
+                }
+            }
+            (MenuInput::Back, _) => return Ok(()),
+            (MenuInput::None, _) => {}
+        }
+    }
+}
+
+fn run_key_bindings_menu(settings: &mut Settings) -> io::Result<()> {
+    // One entry per bindable command, followed by reset and back
+    let reset_index = BINDABLE_COMMANDS.len();
+    let back_index = reset_index + 1;
+    let mut selected = 0;
+    let mut status = String::new();
+    loop {
+        let mut labels: Vec<String> = BINDABLE_COMMANDS
+            .iter()
+            .map(|command| {
+                format!(
+                    "{:<10} {}",
+                    command.get_name(),
+                    settings.key_bindings.get_keys(command).join(", ")
+                )
+            })
+            .collect();
+        labels.push(String::from("Reset to defaults"));
+        labels.push(String::from("Back"));
+        draw_menu(&["KEY BINDINGS"], &labels, selected, &status)?;
+
+        match read_menu_input()? {
+            MenuInput::Up => selected = previous_index(selected, labels.len()),
+            MenuInput::Down => selected = next_index(selected, labels.len()),
+            MenuInput::Select if selected == back_index => return Ok(()),
+            MenuInput::Select => {
+                if selected == reset_index {
+                    settings.key_bindings = KeyBindings::default();
+                } else {
+                    let command = BINDABLE_COMMANDS[selected];
+                    let keys = capture_keys(command.get_name())?;
+                    if keys.is_empty() {
+                        continue;
+                    }
+                    settings.key_bindings.set_keys(&command, keys);
+                }
+                status = save_settings(settings);
+            }
+            MenuInput::Back => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+fn capture_keys(command_name: &str) -> io::Result<Vec<String>> {
+    let mut keys: Vec<String> = Vec::new();
+    loop {
+        execute!(stdout(), MoveTo(0, 0), Clear(ClearType::All))?;
+        print!("Press keys for {}, then Enter\r\n", command_name);
+        print!("Pressing only Enter keeps the current keys\r\n\r\n");
+        print!("{}\r\n", keys.join(", "));
+
+        if let Event::Key(key) = read()? {
+            if key.kind == KeyEventKind::Release {
+                continue;
+            }
+            if key.code == KeyCode::Enter {
+                return Ok(keys);
+            }
+            if let Some(key_name) = get_key_name(&key.code) {
+                if !keys.contains(&key_name) {
+                    keys.push(key_name);
+                }
+            }
+        }
+    }
+}
+
+fn show_results(finished_game: &FinishedGame, mut status: String) -> io::Result<ResultsMenuItem> {
+    let stats = &finished_game.stats;
+    let mode = &finished_game.replay.ruleset.mode;
+    let title = match finished_game.game_end {
+        GameEnd::Finished => "FINISHED",
+        _ => "GAME OVER",
+    };
+    let time = format_score_time(mode, stats.elapsed.as_millis() as u64);
+    let mut header = vec![
+        String::from(title),
+        String::new(),
+        format!("Score      {:>10}", stats.points),
+    ];
+    if mode.get_time_limit().is_some() {
+        header.push(format!(
+            "Score/min  {:>10.0}",
+            stats.get_points_per_minute()
+        ));
+    }
+    header.extend([
+        format!("Lines      {:>10}", stats.lines),
+        format!("Level      {:>10}", stats.level),
+        format!("Time       {:>10}", time),
+        format!("PPS        {:>10.2}", stats.get_pieces_per_second()),
+        format!("Max combo  {:>10}", stats.max_combo),
+        String::new(),
+    ]);
+    for (name, count) in CLEAR_TYPE_NAMES.iter().zip(stats.clear_types.iter()) {
+        header.push(format!("{:<10} {:>10}", name, count));
+    }
+    if mode.uses_guideline_scoring() {
+        header.push(format!("{:<10} {:>10}", "T-spins", stats.t_spins));
+    }
+    if let Some(split_lines) = mode.get_split_lines() {
+        header.push(String::new());
+        for (index, split) in stats.splits.iter().enumerate() {
+            let lines = format!("{} lines", (index + 1) * split_lines);
+            header.push(format!(
+                "{:<10} {:>10}",
+                lines,
+                format_precise_duration(split)
+            ));
+        }
+    }
+    let header: Vec<&str> = header.iter().map(String::as_str).collect();
+    let labels: Vec<String> = RESULTS_MENU_ITEMS.iter().map(results_menu_label).collect();
+
+    let mut selected = 0;
+    loop {
+        draw_menu(&header, &labels, selected, &status)?;
+        match read_menu_input()? {
+            MenuInput::Up => selected = previous_index(selected, RESULTS_MENU_ITEMS.len()),
+            MenuInput::Down => selected = next_index(selected, RESULTS_MENU_ITEMS.len()),
+            MenuInput::Select => match RESULTS_MENU_ITEMS[selected] {
+                ResultsMenuItem::SaveReplay => {
+                    let saved = get_new_replay_path(mode).and_then(|path| {
+                        save_replay(&finished_game.replay, &path)?;
+                        Ok(path)
+                    });
+                    status = match saved {
+                        Ok(path) => format!("Replay saved to {}", path.display()),
+                        Err(error) => error.to_string(),
+                    };
+                }
+                item => return Ok(item),
+            },
+            MenuInput::Back => return Ok(ResultsMenuItem::Menu),
+            _ => {}
+        }
+    }
+}
+
+// Asks for a name when the game made it into the table, returns a status for the results screen
+fn record_high_score(
+    high_scores: &mut HighScoreTable,
+    player_name: &mut String,
+    mode: &GameMode,
+    stats: &GameStats,
+) -> io::Result<String> {
+    if !high_scores.is_high_score(mode, stats) {
+        return Ok(String::new());
+    }
+    let result = if mode.ranks_by_time() {
+        format_precise_duration(&stats.elapsed)
+    } else {
+        stats.points.to_string()
+    };
+    let name = match prompt_name(player_name, &result)? {
+        Some(name) => name,
+        None => return Ok(String::new()),
+    };
+    let rank = high_scores.insert(mode, HighScore::new(&name, stats));
+    *player_name = name;
+    match save_high_scores(high_scores) {
+        Ok(()) => Ok(format!("New high score, rank {}", rank + 1)),
+        Err(error) => Ok(error.to_string()),
+    }
+}
+
+fn prompt_name(default_name: &str, result: &str) -> io::Result<Option<String>> {
+    let mut name = String::from(default_name);
+    loop {
+        execute!(stdout(), MoveTo(0, 0), Clear(ClearType::All))?;
+        print!("NEW HIGH SCORE: {}\r\n\r\n", result);
+        print!("Name: {}\r\n\r\n", name);
+        print!("Press Enter to save, Esc to skip\r\n");
+
+        if let Event::Key(key) = read()? {
+            if key.kind == KeyEventKind::Release {
+                continue;
+            }
+            match key.code {
+                KeyCode::Enter if !name.trim().is_empty() => {
+                    return Ok(Some(String::from(name.trim())));
+                }
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Backspace => {
+                    name.pop();
+                }
+                KeyCode::Char(character)
+                    if !character.is_control() && name.chars().count() < MAX_NAME_LENGTH =>
+                {
+                    name.push(character);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn show_high_scores(high_scores: &HighScoreTable, mut mode: GameMode) -> io::Result<()> {
+    loop {
+        execute!(stdout(), MoveTo(0, 0), Clear(ClearType::All))?;
+        print!("HIGH SCORES: < {} >\r\n\r\n", mode.get_name());
+        let scores = high_scores.get_scores(&mode);
+        if scores.is_empty() {
+            print!("No scores yet\r\n");
+        } else {
+            print!(
+                "    {:<12} {:>8} {:>6} {:>6} {:>9}  Date\r\n",
+                "Name", "Score", "Lines", "Level", "Time"
+            );
+        }
+        for (rank, score) in scores.iter().enumerate() {
+            print!(
+                "{:>2}. {:<12} {:>8} {:>6} {:>6} {:>9}  {}\r\n",
+                rank + 1,
+                score.name,
+                score.points,
+                score.lines,
+                score.level,
+                format_score_time(&mode, score.time_ms),
+                score.date
+            );
+        }
+        print!("\r\nLeft and Right switch modes, Enter returns\r\n");
+
+        match read_menu_input()? {
+            MenuInput::Left => mode = mode.previous(),
+            MenuInput::Right => mode = mode.next(),
+            MenuInput::Select | MenuInput::Back => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+// Times matter most in timed modes, so they get milliseconds
+fn format_score_time(mode: &GameMode, time_ms: u64) -> String {
+    let time = Duration::from_millis(time_ms);
+    if mode.ranks_by_time() {
+        format_precise_duration(&time)
+    } else {
+        format_duration(&time)
+    }
+}
+
+fn save_settings(settings: &Settings) -> String {
+    let saved = match &settings.config_path {
+        Some(path) => save_config(&settings.to_config(), path),
+        None => return String::from("Not saved, no config file was loaded"),
+    };
+    match saved {
+        Ok(()) => String::from("Saved"),
+        Err(error) => error.to_string(),
+    }
+}
+
+fn apply_music_setting(settings: &Settings, audio: &dyn AudioBackend) {
+    audio.set_music_playing(settings.music);
+}
+
+fn apply_volume_settings(settings: &Settings, audio: &dyn AudioBackend) {
+    audio.set_volumes(settings.music_volume, settings.effects_volume);
+}
+
+// Plays the track right away, so it can be heard while choosing
+fn select_track(settings: &mut Settings, audio: &dyn AudioBackend, track: &Track) -> String {
+    settings.track = Some(track.get_name());
+    if let Err(error) = audio.play_track(track) {
+        return error.to_string();
+    }
+    apply_music_setting(settings, audio);
+    save_settings(settings)
+}
+
+fn get_main_menu_items(mode: &GameMode) -> Vec<MainMenuItem> {
+    MAIN_MENU_ITEMS
+        .into_iter()
+        .filter(|item| match item {
+            MainMenuItem::StartLevel => mode.has_start_level(),
+            MainMenuItem::Endless => mode.get_final_level().is_some(),
+            _ => true,
+        })
+        .collect()
+}
+
+fn main_menu_label(item: &MainMenuItem, settings: &Settings) -> String {
+    match item {
+        MainMenuItem::Start => String::from("Start game"),
+        MainMenuItem::Mode => format!("Mode: < {} >", settings.mode.get_name()),
+        MainMenuItem::StartLevel => format!("Start level: < {} >", settings.start_level),
+        MainMenuItem::Endless => format!("Endless marathon: {}", on_off(settings.endless)),
+        MainMenuItem::Options => String::from("Options"),
+        MainMenuItem::HighScores => String::from("High scores"),
+        MainMenuItem::Quit => String::from("Quit"),
+    }
+}
+
+fn options_menu_label(item: &OptionsMenuItem, settings: &Settings, track: &Track) -> String {
+    match item {
+        OptionsMenuItem::Music => format!("Music: {}", on_off(settings.music)),
+        OptionsMenuItem::MusicVolume => format!("Music volume: < {}% >", settings.music_volume),
+        OptionsMenuItem::EffectsVolume => {
+            format!("Effects volume: < {}% >", settings.effects_volume)
+        }
+        OptionsMenuItem::Track => format!("Track: < {} >", track.get_name()),
+        OptionsMenuItem::Animations => format!("Animations: {}", on_off(settings.animations)),
+        OptionsMenuItem::Das => format!("DAS: < {} ms >", settings.handling.das_ms),
+        OptionsMenuItem::Arr => format!("ARR: < {} ms >", settings.handling.arr_ms),
+        OptionsMenuItem::KeyBindings => String::from("Key bindings"),
+        OptionsMenuItem::Back => String::from("Back"),
+    }
+}
+
+fn results_menu_label(item: &ResultsMenuItem) -> String {
+    match item {
+        ResultsMenuItem::Retry => String::from("Retry"),
+        ResultsMenuItem::Menu => String::from("Menu"),
+        ResultsMenuItem::SaveReplay => String::from("Save replay"),
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "On"
+    } else {
+        "Off"
+    }
+}
+
+fn draw_menu(header: &[&str], labels: &[String], selected: usize, footer: &str) -> io::Result<()> {
+    execute!(stdout(), MoveTo(0, 0), Clear(ClearType::All))?;
+    for line in header {
+        print!("{}\r\n", line);
+    }
+    print!("\r\n");
+    for (index, label) in labels.iter().enumerate() {
+        if index == selected {
+            print!("  > {}\r\n", label);
+        } else {
+            print!("    {}\r\n", label);
+        }
+    }
+    print!("\r\n{}\r\n", footer);
+    Ok(())
+}
+
+fn next_index(index: usize, length: usize) -> usize {
+    (index + 1) % length
+}
+
+fn previous_index(index: usize, length: usize) -> usize {
+    (index + length - 1) % length
+}
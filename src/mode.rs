@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameMode {
+    Marathon,
+    Sprint,
+    Ultra,
+}
+
+const GAME_MODES: [GameMode; 3] = [GameMode::Marathon, GameMode::Sprint, GameMode::Ultra];
+const SPRINT_LINES: usize = 40;
+const SPRINT_SPLIT_LINES: usize = 10;
+const ULTRA_SECONDS: u64 = 120;
+const MARATHON_FINAL_LEVEL: usize = 15;
+// The name Marathon had before it got a final level
+const OLD_MARATHON_NAME: &str = "Endless";
+
+impl GameMode {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            GameMode::Marathon => "Marathon",
+            GameMode::Sprint => "Sprint",
+            GameMode::Ultra => "Ultra",
+        }
+    }
+
+    // Lines that finish the game, None when it is played until topping out
+    pub fn get_line_goal(&self) -> Option<usize> {
+        match self {
+            GameMode::Sprint => Some(SPRINT_LINES),
+            GameMode::Marathon | GameMode::Ultra => None,
+        }
+    }
+
+    // The game finishes once this level is completed, unless the ruleset is endless
+    pub fn get_final_level(&self) -> Option<usize> {
+        match self {
+            GameMode::Marathon => Some(MARATHON_FINAL_LEVEL),
+            GameMode::Sprint | GameMode::Ultra => None,
+        }
+    }
+
+    // Sprint times and Ultra scores are only comparable when every game starts at level 1
+    pub fn has_start_level(&self) -> bool {
+        match self {
+            GameMode::Marathon => true,
+            GameMode::Sprint | GameMode::Ultra => false,
+        }
+    }
+
+    // Time after which the game finishes, counted down on the HUD
+    pub fn get_time_limit(&self) -> Option<Duration> {
+        match self {
+            GameMode::Ultra => Some(Duration::from_secs(ULTRA_SECONDS)),
+            GameMode::Marathon | GameMode::Sprint => None,
+        }
+    }
+
+    // The time is taken every time this many lines are cleared
+    pub fn get_split_lines(&self) -> Option<usize> {
+        match self {
+            GameMode::Sprint => Some(SPRINT_SPLIT_LINES),
+            GameMode::Marathon | GameMode::Ultra => None,
+        }
+    }
+
+    // Faster finishes rank higher instead of higher scores
+    pub fn ranks_by_time(&self) -> bool {
+        match self {
+            GameMode::Sprint => true,
+            GameMode::Marathon | GameMode::Ultra => false,
+        }
+    }
+
+    // Guideline scoring rewards T-spins, back-to-back clears, combos and drops,
+    // the other modes keep the classic points per clear
+    pub fn uses_guideline_scoring(&self) -> bool {
+        match self {
+            GameMode::Ultra => true,
+            GameMode::Marathon | GameMode::Sprint => false,
+        }
+    }
+
+    pub fn shows_timer(&self) -> bool {
+        self.ranks_by_time() || self.get_time_limit().is_some()
+    }
+
+    pub fn from_name(name: &str) -> Option<GameMode> {
+        if name.eq_ignore_ascii_case(OLD_MARATHON_NAME) {
+            return Some(GameMode::Marathon);
+        }
+        GAME_MODES
+            .iter()
+            .find(|mode| mode.get_name().eq_ignore_ascii_case(name))
+            .copied()
+    }
+
+    pub fn next(&self) -> GameMode {
+        let index = self.get_index();
+        GAME_MODES[(index + 1) % GAME_MODES.len()]
+    }
+
+    pub fn previous(&self) -> GameMode {
+        let index = self.get_index();
+        GAME_MODES[(index + GAME_MODES.len() - 1) % GAME_MODES.len()]
+    }
+
+    fn get_index(&self) -> usize {
+        GAME_MODES.iter().position(|mode| mode == self).unwrap_or(0)
+    }
+}
@@ -0,0 +1,19 @@
+use std::path::PathBuf;
+
+const APPLICATION_DIRECTORY: &str = "tetris";
+
+pub fn get_config_directory() -> Option<PathBuf> {
+    get_xdg_directory("XDG_CONFIG_HOME", ".config")
+}
+
+pub fn get_data_directory() -> Option<PathBuf> {
+    get_xdg_directory("XDG_DATA_HOME", ".local/share")
+}
+
+fn get_xdg_directory(variable: &str, home_fallback: &str) -> Option<PathBuf> {
+    let base_directory = match std::env::var_os(variable) {
+        Some(directory) if !directory.is_empty() => PathBuf::from(directory),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(home_fallback),
+    };
+    Some(base_directory.join(APPLICATION_DIRECTORY))
+}
@@ -0,0 +1,190 @@
+use rodio::{dynamic_mixer::mixer, Source};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::{
+    audio::{create_music_source, AudioError, Track},
+    engine::{Engine, FRAMES_PER_SECOND},
+    input_source::ReplayInput,
+    replay::{advance_replay, Replay},
+    settings::Settings,
+    sfx::{create_effect_source, get_sound_effect},
+    synth::{Tempo, SAMPLE_RATE},
+};
+
+// Lets the last sound effect ring out after the game ends
+const EFFECT_TAIL_SECONDS: f64 = 1.0;
+const BITS_PER_SAMPLE: u16 = 16;
+
+#[derive(Debug)]
+pub enum RenderError {
+    Io(std::io::Error),
+    Audio(AudioError),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::Io(error) => write!(f, "Failed to write WAV file: {}", error),
+            RenderError::Audio(error) => write!(f, "Failed to render audio: {}", error),
+        }
+    }
+}
+
+impl Error for RenderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RenderError::Io(error) => Some(error),
+            RenderError::Audio(error) => Some(error),
+        }
+    }
+}
+
+impl From<std::io::Error> for RenderError {
+    fn from(error: std::io::Error) -> Self {
+        RenderError::Io(error)
+    }
+}
+
+impl From<AudioError> for RenderError {
+    fn from(error: AudioError) -> Self {
+        RenderError::Audio(error)
+    }
+}
+
+// Mixes like the audio output does, with the music at the normal tempo
+pub fn render_music(
+    track: &Track,
+    settings: &Settings,
+    seconds: f64,
+    path: &Path,
+) -> Result<(), RenderError> {
+    let (controller, output) = mixer::<f32>(1, SAMPLE_RATE);
+    let music = create_music_source(track, &Tempo::new(1.0))?;
+    controller.add(music.amplify(settings.music_volume as f32 / 100.0));
+    write_wav(path, &take_samples(output, seconds))?;
+    Ok(())
+}
+
+// Replays the game and plays every sound effect at the frame its event happened.
+// Effects are only added to the mixer when their frame is reached, so it never has to go
+// through more than the ones still playing
+pub fn render_replay_effects(
+    replay: &Replay,
+    settings: &Settings,
+    path: &Path,
+) -> Result<(), RenderError> {
+    let (controller, mut output) = mixer::<f32>(1, SAMPLE_RATE);
+    let mut samples: Vec<f32> = Vec::new();
+    let volume = settings.effects_volume as f32 / 100.0;
+    let mut engine = Engine::new(&replay.ruleset);
+    let mut input_source = ReplayInput::new(&replay.inputs);
+    let last_frame = match replay.result {
+        Some(result) => result.frames,
+        None => replay.inputs.last().map_or(0, |input| input.frame + 1),
+    };
+    while engine.get_game_end().is_none() && engine.get_frame() < last_frame {
+        mix_until(
+            &mut output,
+            &mut samples,
+            get_frame_sample(engine.get_frame()),
+        );
+        for event in advance_replay(&mut engine, &mut input_source)? {
+            if let Some(effect) = get_sound_effect(&event) {
+                controller.add(create_effect_source(effect).amplify(volume));
+            }
+        }
+    }
+    let tail_samples = (EFFECT_TAIL_SECONDS * SAMPLE_RATE as f64) as usize;
+    let sample_count = get_frame_sample(engine.get_frame()) + tail_samples;
+    mix_until(&mut output, &mut samples, sample_count);
+    write_wav(path, &samples)?;
+    Ok(())
+}
+
+fn get_frame_sample(frame: u64) -> usize {
+    (frame * SAMPLE_RATE as u64 / FRAMES_PER_SECOND) as usize
+}
+
+// The mixer has no samples while nothing plays, that is silence
+fn mix_until(output: &mut impl Iterator<Item = f32>, samples: &mut Vec<f32>, sample_count: usize) {
+    while samples.len() < sample_count {
+        samples.push(output.next().unwrap_or(0.0));
+    }
+}
+
+// The mixer stops when its sources run out, the rest is filled with silence
+fn take_samples(source: impl Iterator<Item = f32>, seconds: f64) -> Vec<f32> {
+    let sample_count = (seconds * SAMPLE_RATE as f64) as usize;
+    let mut samples: Vec<f32> = source.take(sample_count).collect();
+    samples.resize(sample_count, 0.0);
+    samples
+}
+
+// Writes mono 16-bit PCM
+fn write_wav(path: &Path, samples: &[f32]) -> std::io::Result<()> {
+    let block_align = BITS_PER_SAMPLE / 8;
+    let data_size = samples.len() as u32 * block_align as u32;
+    let mut bytes: Vec<u8> = Vec::with_capacity(44 + data_size as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_size).to_le_bytes());
+    bytes.extend_from_slice(b"WAVE");
+    bytes.extend_from_slice(b"fmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, one channel
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    bytes.extend_from_slice(&(SAMPLE_RATE * block_align as u32).to_le_bytes());
+    bytes.extend_from_slice(&block_align.to_le_bytes());
+    bytes.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    fs::write(path, bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        engine::Ruleset,
+        input::{InputCommand, KeyAction},
+        mode::GameMode,
+    };
+
+    const DROP_INTERVAL: u64 = 30;
+
+    #[test]
+    fn effects_start_at_their_frame() {
+        let ruleset = Ruleset::new(3, GameMode::Marathon);
+        let mut replay = Replay::new(&ruleset);
+        for drop in 1..=3 {
+            replay.record(
+                drop * DROP_INTERVAL,
+                InputCommand::HardDrop,
+                KeyAction::Press,
+            );
+        }
+        let path = std::env::temp_dir().join(format!("tetris-render-{}.wav", std::process::id()));
+        render_replay_effects(&replay, &Settings::default(), &path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let samples: Vec<i16> = bytes[44..]
+            .chunks(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        let expected_samples = get_frame_sample(3 * DROP_INTERVAL + 1)
+            + (EFFECT_TAIL_SECONDS * SAMPLE_RATE as f64) as usize;
+        assert_eq!(samples.len(), expected_samples);
+        let drop_sample = get_frame_sample(DROP_INTERVAL);
+        let first_sound = samples.iter().position(|sample| *sample != 0).unwrap();
+        assert!((drop_sample..drop_sample + 10).contains(&first_sound));
+    }
+}
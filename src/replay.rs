@@ -0,0 +1,623 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, stdout};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crossterm::{
+    cursor::MoveTo,
+    event::{poll, read, Event, KeyCode, KeyEventKind},
+    execute,
+    terminal::{Clear, ClearType},
+};
+
+use crate::{
+    board::{
+        display_board, get_board_hash, MAX_BOARD_HEIGHT, MAX_BOARD_WIDTH, MIN_BOARD_HEIGHT,
+        MIN_BOARD_WIDTH,
+    },
+    engine::{Engine, Ruleset, FRAME_DURATION, MAX_LEVEL},
+    events::GameEvent,
+    handling::{MAX_ARR_MILLISECONDS, MAX_DAS_MILLISECONDS},
+    input::{InputCommand, KeyAction},
+    input_source::{InputSource, ReplayInput, TimedInput},
+    menu::wait_for_confirmation,
+    mode::GameMode,
+    paths::get_data_directory,
+};
+
+const REPLAY_HEADER: &str = "tetris-replay";
+const REPLAY_VERSION: u32 = 2;
+const REPLAY_DIRECTORY: &str = "replays";
+const MIN_PLAYBACK_SPEED: f64 = 0.25;
+const MAX_PLAYBACK_SPEED: f64 = 8.0;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Parse(usize, String),
+    UnsupportedVersion(u32),
+    MissingResult,
+    NoDataDirectory,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(error) => write!(f, "Failed to access replay file: {}", error),
+            ReplayError::Parse(line, message) => {
+                write!(f, "Invalid replay file on line {}: {}", line, message)
+            }
+            ReplayError::UnsupportedVersion(version) => {
+                write!(f, "Replay version {} is not supported", version)
+            }
+            ReplayError::MissingResult => write!(f, "Replay does not contain a result"),
+            ReplayError::NoDataDirectory => write!(f, "Could not find a data directory"),
+        }
+    }
+}
+
+impl Error for ReplayError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReplayError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(error: std::io::Error) -> Self {
+        ReplayError::Io(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayResult {
+    pub points: usize,
+    pub lines: usize,
+    pub frames: u64,
+    pub board_hash: u64,
+}
+
+impl ReplayResult {
+    pub fn from_engine(engine: &Engine) -> ReplayResult {
+        ReplayResult {
+            points: engine.get_stats().points,
+            lines: engine.get_stats().lines,
+            frames: engine.get_frame(),
+            board_hash: get_board_hash(engine.get_board()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub ruleset: Ruleset,
+    pub inputs: Vec<TimedInput>,
+    pub result: Option<ReplayResult>,
+}
+
+impl Replay {
+    pub fn new(ruleset: &Ruleset) -> Replay {
+        Replay {
+            ruleset: ruleset.clone(),
+            inputs: Vec::new(),
+            result: None,
+        }
+    }
+
+    pub fn record(&mut self, frame: u64, command: InputCommand, action: KeyAction) {
+        // Unbound keys do nothing, so they are not worth storing
+        if command != InputCommand::None {
+            self.inputs.push(TimedInput {
+                frame,
+                command,
+                action,
+            });
+        }
+    }
+}
+
+// The file is a short header followed by one line per input,
+// each input stores the number of frames since the previous one
+pub fn save_replay(replay: &Replay, path: &Path) -> Result<(), ReplayError> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    fs::write(path, format_replay(replay))?;
+    Ok(())
+}
+
+pub fn load_replay(path: &Path) -> Result<Replay, ReplayError> {
+    parse_replay(&fs::read_to_string(path)?)
+}
+
+pub fn format_replay(replay: &Replay) -> String {
+    let ruleset = &replay.ruleset;
+    let mut contents = format!("{} {}\n", REPLAY_HEADER, REPLAY_VERSION);
+    contents.push_str(&format!("seed {}\n", ruleset.seed));
+    contents.push_str(&format!("mode {}\n", ruleset.mode.get_name()));
+    contents.push_str(&format!("das {}\n", ruleset.handling.das_ms));
+    contents.push_str(&format!("arr {}\n", ruleset.handling.arr_ms));
+    contents.push_str(&format!(
+        "release_events {}\n",
+        u8::from(ruleset.key_release_events)
+    ));
+    contents.push_str(&format!("width {}\n", ruleset.board_size.width));
+    contents.push_str(&format!("height {}\n", ruleset.board_size.height));
+    contents.push_str(&format!("start_level {}\n", ruleset.start_level));
+    contents.push_str(&format!("endless {}\n", u8::from(ruleset.endless)));
+    if let Some(result) = &replay.result {
+        contents.push_str(&format!("points {}\n", result.points));
+        contents.push_str(&format!("lines {}\n", result.lines));
+        contents.push_str(&format!("frames {}\n", result.frames));
+        contents.push_str(&format!("board {:016x}\n", result.board_hash));
+    }
+    contents.push_str("inputs\n");
+    let mut previous_frame = 0;
+    for input in &replay.inputs {
+        contents.push_str(&format!(
+            "{} {} {}\n",
+            input.frame - previous_frame,
+            get_command_code(&input.command),
+            get_action_code(&input.action)
+        ));
+        previous_frame = input.frame;
+    }
+    contents
+}
+
+pub fn parse_replay(contents: &str) -> Result<Replay, ReplayError> {
+    let mut lines = contents
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line));
+
+    let (_, first_line) = lines
+        .next()
+        .ok_or_else(|| ReplayError::Parse(1, String::from("file is empty")))?;
+    let version = match first_line.split_once(' ') {
+        Some((REPLAY_HEADER, version)) => version
+            .parse::<u32>()
+            .map_err(|_| ReplayError::Parse(1, String::from("invalid version")))?,
+        _ => return Err(ReplayError::Parse(1, String::from("not a replay file"))),
+    };
+    if version != REPLAY_VERSION {
+        return Err(ReplayError::UnsupportedVersion(version));
+    }
+
+    let mut ruleset = Ruleset::new(0, GameMode::Marathon);
+    let mut result = ReplayResult {
+        points: 0,
+        lines: 0,
+        frames: 0,
+        board_hash: 0,
+    };
+    let mut has_result = false;
+    let mut start_level_line = 0;
+    for (line_number, line) in lines.by_ref() {
+        if line == "inputs" {
+            break;
+        }
+        let (key, value) = line
+            .split_once(' ')
+            .ok_or_else(|| ReplayError::Parse(line_number, String::from("expected a value")))?;
+        let invalid_value = || ReplayError::Parse(line_number, format!("invalid {}", key));
+        match key {
+            "seed" => ruleset.seed = value.parse().map_err(|_| invalid_value())?,
+            "mode" => ruleset.mode = GameMode::from_name(value).ok_or_else(invalid_value)?,
+            "das" => {
+                ruleset.handling.das_ms = value
+                    .parse()
+                    .ok()
+                    .filter(|das| *das <= MAX_DAS_MILLISECONDS)
+                    .ok_or_else(invalid_value)?;
+            }
+            "arr" => {
+                ruleset.handling.arr_ms = value
+                    .parse()
+                    .ok()
+                    .filter(|arr| *arr <= MAX_ARR_MILLISECONDS)
+                    .ok_or_else(invalid_value)?;
+            }
+            "release_events" => ruleset.key_release_events = value == "1",
+            "width" => {
+                ruleset.board_size.width = value
+                    .parse()
+                    .ok()
+                    .filter(|width| (MIN_BOARD_WIDTH..=MAX_BOARD_WIDTH).contains(width))
+                    .ok_or_else(invalid_value)?;
+            }
+            "height" => {
+                ruleset.board_size.height = value
+                    .parse()
+                    .ok()
+                    .filter(|height| (MIN_BOARD_HEIGHT..=MAX_BOARD_HEIGHT).contains(height))
+                    .ok_or_else(invalid_value)?;
+            }
+            "start_level" => {
+                ruleset.start_level = value
+                    .parse()
+                    .ok()
+                    .filter(|level| (1..=MAX_LEVEL).contains(level))
+                    .ok_or_else(invalid_value)?;
+                start_level_line = line_number;
+            }
+            "endless" => ruleset.endless = value == "1",
+            "points" => result.points = value.parse().map_err(|_| invalid_value())?,
+            "lines" => result.lines = value.parse().map_err(|_| invalid_value())?,
+            "frames" => result.frames = value.parse().map_err(|_| invalid_value())?,
+            "board" => {
+                result.board_hash = u64::from_str_radix(value, 16).map_err(|_| invalid_value())?;
+                has_result = true;
+            }
+            _ => {
+                return Err(ReplayError::Parse(
+                    line_number,
+                    format!("unknown key {}", key),
+                ))
+            }
+        }
+    }
+
+    if ruleset.start_level != 1 && !ruleset.mode.has_start_level() {
+        return Err(ReplayError::Parse(
+            start_level_line,
+            format!("{} always starts at level 1", ruleset.mode.get_name()),
+        ));
+    }
+
+    let mut replay = Replay::new(&ruleset);
+    if has_result {
+        replay.result = Some(result);
+    }
+    let mut frame: u64 = 0;
+    for (line_number, line) in lines {
+        let invalid_input = || ReplayError::Parse(line_number, String::from("invalid input"));
+        let mut parts = line.split(' ');
+        let frame_delta: u64 = parts
+            .next()
+            .and_then(|delta| delta.parse().ok())
+            .ok_or_else(invalid_input)?;
+        let command = parts
+            .next()
+            .and_then(parse_command_code)
+            .ok_or_else(invalid_input)?;
+        let action = parts
+            .next()
+            .and_then(parse_action_code)
+            .ok_or_else(invalid_input)?;
+        frame = frame.checked_add(frame_delta).ok_or_else(invalid_input)?;
+        replay.record(frame, command, action);
+    }
+    Ok(replay)
+}
+
+pub fn get_new_replay_path(mode: &GameMode) -> Result<PathBuf, ReplayError> {
+    let directory = get_data_directory().ok_or(ReplayError::NoDataDirectory)?;
+    let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(time) => time.as_secs(),
+        Err(_) => 0,
+    };
+    Ok(directory.join(REPLAY_DIRECTORY).join(format!(
+        "{}-{}.replay",
+        mode.get_name().to_lowercase(),
+        timestamp
+    )))
+}
+
+pub fn play_replay(replay: &Replay) -> io::Result<()> {
+    let mut engine = Engine::new(&replay.ruleset);
+    let mut input_source = ReplayInput::new(&replay.inputs);
+    let mut speed: f64 = 1.0;
+    let mut paused = false;
+    let mut accumulated_time = Duration::ZERO;
+    let mut previous_time = Instant::now();
+
+    execute!(stdout(), Clear(ClearType::All))?;
+    while engine.get_game_end().is_none() {
+        let mut step = false;
+        if poll(FRAME_DURATION)? {
+            if let Event::Key(key) = read()? {
+                if key.kind != KeyEventKind::Release {
+                    match key.code {
+                        KeyCode::Char(' ') => paused = !paused,
+                        KeyCode::Char('+') | KeyCode::Right => {
+                            speed = (speed * 2.0).min(MAX_PLAYBACK_SPEED);
+                        }
+                        KeyCode::Char('-') | KeyCode::Left => {
+                            speed = (speed / 2.0).max(MIN_PLAYBACK_SPEED);
+                        }
+                        KeyCode::Char('.') if paused => step = true,
+                        KeyCode::Esc | KeyCode::Char('q') => return Ok(()),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let now = Instant::now();
+        if !paused {
+            accumulated_time += (now - previous_time).mul_f64(speed);
+        }
+        previous_time = now;
+        let mut frames = 0;
+        while accumulated_time >= FRAME_DURATION {
+            accumulated_time -= FRAME_DURATION;
+            frames += 1;
+        }
+        if step {
+            frames = 1;
+        }
+
+        for _ in 0..frames {
+            advance_replay(&mut engine, &mut input_source)?;
+            if engine.get_game_end().is_some() {
+                break;
+            }
+        }
+
+        execute!(stdout(), MoveTo(0, 0))?;
+        display_board(engine.get_board());
+        print!(
+            "Frame {:>7}  Speed {:>5.2}x  {:<6}\r\n",
+            engine.get_frame(),
+            speed,
+            if paused { "Paused" } else { "" }
+        );
+        print!("Space pause  +/- speed  . step  Esc quit\r\n");
+    }
+
+    print!(
+        "\r\nReplay finished, points: {}\r\n",
+        engine.get_stats().points
+    );
+    print!("Press Enter to return\r\n");
+    wait_for_confirmation()
+}
+
+// Re-simulates the replay without a terminal and lists every value that differs
+pub fn verify_replay(replay: &Replay) -> Result<Vec<String>, ReplayError> {
+    let expected = replay.result.ok_or(ReplayError::MissingResult)?;
+    let mut engine = Engine::new(&replay.ruleset);
+    let mut input_source = ReplayInput::new(&replay.inputs);
+    while engine.get_game_end().is_none() && engine.get_frame() < expected.frames {
+        advance_replay(&mut engine, &mut input_source)?;
+    }
+
+    let actual = ReplayResult::from_engine(&engine);
+    let mut mismatches = Vec::new();
+    if actual.points != expected.points {
+        mismatches.push(format!(
+            "points: expected {}, got {}",
+            expected.points, actual.points
+        ));
+    }
+    if actual.lines != expected.lines {
+        mismatches.push(format!(
+            "lines: expected {}, got {}",
+            expected.lines, actual.lines
+        ));
+    }
+    if actual.frames != expected.frames {
+        mismatches.push(format!(
+            "frames: expected {}, got {}",
+            expected.frames, actual.frames
+        ));
+    }
+    if actual.board_hash != expected.board_hash {
+        mismatches.push(format!(
+            "board: expected {:016x}, got {:016x}",
+            expected.board_hash, actual.board_hash
+        ));
+    }
+    Ok(mismatches)
+}
+
+// Applies the inputs recorded in the current frame before ticking it, returns the frame's events
+pub fn advance_replay(
+    engine: &mut Engine,
+    input_source: &mut dyn InputSource,
+) -> io::Result<Vec<GameEvent>> {
+    while let Some(input) = input_source.next_input(engine.get_frame(), Duration::ZERO)? {
+        engine.handle_input(input.command, input.action);
+    }
+    engine.tick();
+    Ok(engine.take_events())
+}
+
+fn get_command_code(command: &InputCommand) -> &'static str {
+    match command {
+        InputCommand::Left => "L",
+        InputCommand::Right => "R",
+        InputCommand::Down => "D",
+        InputCommand::RotateCw => "CW",
+        InputCommand::RotateCcw => "CCW",
+        InputCommand::Rotate180 => "180",
+        InputCommand::HardDrop => "HD",
+        InputCommand::Suspend => "S",
+        InputCommand::Mute => "M",
+        InputCommand::Exit => "X",
+        InputCommand::None => "-",
+    }
+}
+
+fn parse_command_code(code: &str) -> Option<InputCommand> {
+    match code {
+        "L" => Some(InputCommand::Left),
+        "R" => Some(InputCommand::Right),
+        "D" => Some(InputCommand::Down),
+        "CW" => Some(InputCommand::RotateCw),
+        "CCW" => Some(InputCommand::RotateCcw),
+        "180" => Some(InputCommand::Rotate180),
+        "HD" => Some(InputCommand::HardDrop),
+        "S" => Some(InputCommand::Suspend),
+        "M" => Some(InputCommand::Mute),
+        "X" => Some(InputCommand::Exit),
+        "-" => Some(InputCommand::None),
+        _ => None,
+    }
+}
+
+fn get_action_code(action: &KeyAction) -> &'static str {
+    match action {
+        KeyAction::Press => "p",
+        KeyAction::Repeat => "r",
+        KeyAction::Release => "u",
+    }
+}
+
+fn parse_action_code(code: &str) -> Option<KeyAction> {
+    match code {
+        "p" => Some(KeyAction::Press),
+        "r" => Some(KeyAction::Repeat),
+        "u" => Some(KeyAction::Release),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: [InputCommand; 5] = [
+        InputCommand::Left,
+        InputCommand::RotateCw,
+        InputCommand::HardDrop,
+        InputCommand::Right,
+        InputCommand::HardDrop,
+    ];
+    const SCRIPT_INTERVAL: u64 = 20;
+    const SCRIPT_FRAMES: u64 = 1200;
+
+    // Plays and records a game the way the game loop does, with an input every few frames
+    fn create_scripted_replay() -> Replay {
+        let ruleset = Ruleset::new(7, GameMode::Ultra);
+        let mut engine = Engine::new(&ruleset);
+        let mut replay = Replay::new(&ruleset);
+        while engine.get_game_end().is_none() && engine.get_frame() < SCRIPT_FRAMES {
+            if engine.get_frame().is_multiple_of(SCRIPT_INTERVAL) {
+                let index = (engine.get_frame() / SCRIPT_INTERVAL) as usize;
+                let command = SCRIPT[index % SCRIPT.len()];
+                replay.record(engine.get_frame(), command, KeyAction::Press);
+                engine.handle_input(command, KeyAction::Press);
+            }
+            engine.tick();
+        }
+        replay.result = Some(ReplayResult::from_engine(&engine));
+        replay
+    }
+
+    #[test]
+    fn formatted_replay_verifies() {
+        let replay = create_scripted_replay();
+        let parsed = parse_replay(&format_replay(&replay)).unwrap();
+        assert_eq!(parsed.inputs.len(), replay.inputs.len());
+        assert_eq!(parsed.result, replay.result);
+        assert!(verify_replay(&parsed).unwrap().is_empty());
+    }
+
+    #[test]
+    fn tampered_points_are_reported() {
+        let replay = create_scripted_replay();
+        let points = replay.result.unwrap().points;
+        assert!(points > 0);
+        let contents = format_replay(&replay).replace(
+            &format!("points {}\n", points),
+            &format!("points {}\n", points + 1),
+        );
+        let mismatches = verify_replay(&parse_replay(&contents).unwrap()).unwrap();
+        assert_eq!(
+            mismatches,
+            [format!("points: expected {}, got {}", points + 1, points)]
+        );
+    }
+
+    #[test]
+    fn replay_without_result_is_not_verified() {
+        let mut replay = create_scripted_replay();
+        replay.result = None;
+        assert!(matches!(
+            verify_replay(&replay),
+            Err(ReplayError::MissingResult)
+        ));
+    }
+
+    #[test]
+    fn unsupported_version_is_rejected() {
+        let contents = format_replay(&create_scripted_replay()).replacen(
+            &format!("{} {}", REPLAY_HEADER, REPLAY_VERSION),
+            &format!("{} 1", REPLAY_HEADER),
+            1,
+        );
+        assert!(matches!(
+            parse_replay(&contents),
+            Err(ReplayError::UnsupportedVersion(1))
+        ));
+    }
+
+    fn get_parse_error(contents: &str) -> (usize, String) {
+        match parse_replay(contents) {
+            Err(ReplayError::Parse(line, message)) => (line, message),
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn unknown_key_is_rejected() {
+        let contents = format!(
+            "{} {}\nseed 1\nspeed 2\ninputs\n",
+            REPLAY_HEADER, REPLAY_VERSION
+        );
+        assert_eq!(
+            get_parse_error(&contents),
+            (3, String::from("unknown key speed"))
+        );
+    }
+
+    #[test]
+    fn out_of_range_handling_is_rejected() {
+        let contents = format!(
+            "{} {}\ndas {}\ninputs\n",
+            REPLAY_HEADER,
+            REPLAY_VERSION,
+            u64::MAX
+        );
+        assert_eq!(get_parse_error(&contents), (2, String::from("invalid das")));
+        let contents = format!(
+            "{} {}\narr {}\ninputs\n",
+            REPLAY_HEADER,
+            REPLAY_VERSION,
+            MAX_ARR_MILLISECONDS + 1
+        );
+        assert_eq!(get_parse_error(&contents), (2, String::from("invalid arr")));
+    }
+
+    #[test]
+    fn start_level_is_rejected_outside_marathon() {
+        let contents = format!(
+            "{} {}\nmode Ultra\nstart_level 15\ninputs\n",
+            REPLAY_HEADER, REPLAY_VERSION
+        );
+        assert_eq!(
+            get_parse_error(&contents),
+            (3, String::from("Ultra always starts at level 1"))
+        );
+    }
+
+    #[test]
+    fn overflowing_frame_is_rejected() {
+        let contents = format!(
+            "{} {}\ninputs\n1 L p\n{} R p\n",
+            REPLAY_HEADER,
+            REPLAY_VERSION,
+            u64::MAX
+        );
+        assert_eq!(
+            get_parse_error(&contents),
+            (4, String::from("invalid input"))
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::{
+    engine::{Engine, EngineState},
+    input::KeyAction,
+    paths::get_data_directory,
+    replay::{format_replay, parse_replay, Replay, ReplayError},
+};
+
+const SAVE_FILENAME: &str = "suspended.toml";
+// Raised whenever the engine state changes, saves from older versions cannot be resumed
+const SAVE_VERSION: u32 = 4;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
+    Replay(ReplayError),
+    UnsupportedVersion(u32),
+    InvalidState,
+    NoSavedGame,
+    NoDataDirectory,
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(error) => write!(f, "Failed to access saved game: {}", error),
+            SaveError::Parse(error) => write!(f, "Failed to parse saved game: {}", error),
+            SaveError::Serialize(error) => write!(f, "Failed to write saved game: {}", error),
+            SaveError::Replay(error) => write!(f, "Saved game has a broken replay: {}", error),
+            SaveError::UnsupportedVersion(version) => {
+                write!(f, "Saved game version {} is not supported", version)
+            }
+            SaveError::InvalidState => write!(f, "Saved game does not fit the board"),
+            SaveError::NoSavedGame => write!(f, "There is no suspended game"),
+            SaveError::NoDataDirectory => write!(f, "Could not find a data directory"),
+        }
+    }
+}
+
+impl Error for SaveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SaveError::Io(error) => Some(error),
+            SaveError::Parse(error) => Some(error),
+            SaveError::Serialize(error) => Some(error),
+            SaveError::Replay(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(error: std::io::Error) -> Self {
+        SaveError::Io(error)
+    }
+}
+
+impl From<toml::de::Error> for SaveError {
+    fn from(error: toml::de::Error) -> Self {
+        SaveError::Parse(error)
+    }
+}
+
+impl From<toml::ser::Error> for SaveError {
+    fn from(error: toml::ser::Error) -> Self {
+        SaveError::Serialize(error)
+    }
+}
+
+impl From<ReplayError> for SaveError {
+    fn from(error: ReplayError) -> Self {
+        SaveError::Replay(error)
+    }
+}
+
+// Only the version is read first, so saves from other versions are rejected
+// before their contents are parsed
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+// The replay carries the ruleset and keeps recording once the game is resumed
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    version: u32,
+    replay: String,
+    state: EngineState,
+}
+
+pub struct SavedGame {
+    pub engine: Engine,
+    pub replay: Replay,
+}
+
+pub fn save_game(engine: &Engine, replay: &Replay) -> Result<(), SaveError> {
+    let path = get_save_path()?;
+    let save_file = SaveFile {
+        version: SAVE_VERSION,
+        replay: format_replay(replay),
+        state: engine.get_state(),
+    };
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    fs::write(path, toml::to_string_pretty(&save_file)?)?;
+    Ok(())
+}
+
+// A suspended game can only be resumed once, so the file is removed after loading
+pub fn take_saved_game() -> Result<SavedGame, SaveError> {
+    let path = get_save_path()?;
+    if !path.exists() {
+        return Err(SaveError::NoSavedGame);
+    }
+    let contents = fs::read_to_string(&path)?;
+    let header: SaveHeader = toml::from_str(&contents)?;
+    if header.version != SAVE_VERSION {
+        return Err(SaveError::UnsupportedVersion(header.version));
+    }
+    let save_file: SaveFile = toml::from_str(&contents)?;
+    let replay = parse_replay(&save_file.replay)?;
+    let engine =
+        Engine::from_state(&replay.ruleset, save_file.state).ok_or(SaveError::InvalidState)?;
+    fs::remove_file(path)?;
+    let mut saved_game = SavedGame { engine, replay };
+    release_held_directions(&mut saved_game);
+    Ok(saved_game)
+}
+
+// Keys held while suspending are never released, the release is recorded
+// so the replay plays out the same way
+fn release_held_directions(saved_game: &mut SavedGame) {
+    let frame = saved_game.engine.get_frame();
+    for direction in saved_game.engine.get_held_directions().to_vec() {
+        saved_game
+            .replay
+            .record(frame, direction, KeyAction::Release);
+        saved_game
+            .engine
+            .handle_input(direction, KeyAction::Release);
+    }
+}
+
+fn get_save_path() -> Result<PathBuf, SaveError> {
+    match get_data_directory() {
+        Some(directory) => Ok(directory.join(SAVE_FILENAME)),
+        None => Err(SaveError::NoDataDirectory),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{engine::Ruleset, input::InputCommand, mode::GameMode};
+
+    #[test]
+    fn held_directions_are_released_on_resume() {
+        let mut ruleset = Ruleset::new(1, GameMode::Marathon);
+        ruleset.key_release_events = true;
+        let mut saved_game = SavedGame {
+            engine: Engine::new(&ruleset),
+            replay: Replay::new(&ruleset),
+        };
+        saved_game.engine.tick();
+        saved_game
+            .engine
+            .handle_input(InputCommand::Left, KeyAction::Press);
+        assert_eq!(
+            saved_game.engine.get_held_directions(),
+            [InputCommand::Left]
+        );
+
+        release_held_directions(&mut saved_game);
+        assert!(saved_game.engine.get_held_directions().is_empty());
+        let input = saved_game.replay.inputs.last().unwrap();
+        assert_eq!(
+            (input.frame, input.command, input.action),
+            (1, InputCommand::Left, KeyAction::Release)
+        );
+    }
+}
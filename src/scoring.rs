@@ -0,0 +1,29 @@
+// Guideline scoring, all points are multiplied by the level
+const LINE_CLEAR_POINTS: [usize; 5] = [0, 100, 300, 500, 800];
+// T-spins score even without clearing a line
+const T_SPIN_POINTS: [usize; 4] = [400, 800, 1200, 1600];
+const COMBO_POINTS: usize = 50;
+pub const SOFT_DROP_POINTS: usize = 1;
+pub const HARD_DROP_POINTS: usize = 2;
+
+// Tetrises and T-spins that clear lines, a streak of them earns the back-to-back bonus
+pub fn is_difficult_clear(lines: usize, t_spin: bool) -> bool {
+    lines >= 4 || (t_spin && lines > 0)
+}
+
+pub fn get_clear_points(lines: usize, t_spin: bool, back_to_back: bool, level: usize) -> usize {
+    let points = if t_spin {
+        T_SPIN_POINTS[lines.min(T_SPIN_POINTS.len() - 1)]
+    } else {
+        LINE_CLEAR_POINTS[lines.min(LINE_CLEAR_POINTS.len() - 1)]
+    };
+    if back_to_back {
+        points * 3 / 2 * level
+    } else {
+        points * level
+    }
+}
+
+pub fn get_combo_points(combo: usize, level: usize) -> usize {
+    COMBO_POINTS * combo * level
+}
@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+
+use crate::{
+    board::BoardSize, config::Config, handling::Handling, input::KeyBindings, mode::GameMode,
+};
+
+// Defaults, overridden by the config file, overridden by command-line options
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub mode: GameMode,
+    pub music: bool,
+    pub animations: bool,
+    pub key_bindings: KeyBindings,
+    pub handling: Handling,
+    // Fixed seed for every game, a new one is generated for each game when None
+    pub seed: Option<u64>,
+    pub start_level: usize,
+    pub endless: bool,
+    pub board_size: BoardSize,
+    pub music_file: Option<PathBuf>,
+    pub music_speed_step: f32,
+    pub music_max_tempo: f32,
+    pub music_volume: u8,
+    pub effects_volume: u8,
+    pub playlist_files: Vec<PathBuf>,
+    pub track: Option<String>,
+    pub terminal_width: u16,
+    pub terminal_height: u16,
+    // None when there is no config directory to save to
+    pub config_path: Option<PathBuf>,
+    // The config as loaded, so saving does not write command-line overrides back
+    config: Config,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings::from_config(Config::default(), None)
+    }
+}
+
+impl Settings {
+    pub fn from_config(config: Config, config_path: Option<PathBuf>) -> Settings {
+        Settings {
+            mode: GameMode::from_name(&config.game.mode).unwrap_or(GameMode::Marathon),
+            music: config.audio.music,
+            animations: config.display.animations,
+            key_bindings: config.keys.clone(),
+            handling: config.handling,
+            seed: None,
+            start_level: config.game.start_level,
+            endless: config.game.endless,
+            board_size: BoardSize {
+                width: config.game.width,
+                height: config.game.height,
+            },
+            music_file: config.audio.music_file.clone(),
+            music_speed_step: config.audio.speed_step as f32,
+            music_max_tempo: config.audio.max_tempo as f32,
+            music_volume: config.audio.music_volume,
+            effects_volume: config.audio.effects_volume,
+            playlist_files: config.audio.playlist.clone(),
+            track: config.audio.track.clone(),
+            terminal_width: config.display.terminal_width,
+            terminal_height: config.display.terminal_height,
+            config_path,
+            config,
+        }
+    }
+
+    // Only what can be changed in the options menu is taken from the settings
+    pub fn to_config(&self) -> Config {
+        let mut config = self.config.clone();
+        config.audio.music = self.music;
+        config.audio.music_volume = self.music_volume;
+        config.audio.effects_volume = self.effects_volume;
+        // --music clears the track without the player choosing one, that is not saved
+        if self.track.is_some() {
+            config.audio.track = self.track.clone();
+        }
+        config.display.animations = self.animations;
+        config.keys = self.key_bindings.clone();
+        config.handling = self.handling;
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn music_override_keeps_saved_track() {
+        let mut config = Config::default();
+        config.audio.track = Some(String::from("theme"));
+        let mut settings = Settings::from_config(config, None);
+        settings.track = None;
+        assert_eq!(settings.to_config().audio.track.as_deref(), Some("theme"));
+
+        settings.track = Some(String::from("other"));
+        assert_eq!(settings.to_config().audio.track.as_deref(), Some("other"));
+    }
+}
@@ -0,0 +1,109 @@
+use rodio::Source;
+
+use crate::{
+    events::GameEvent,
+    synth::{Envelope, Note, Voice, Waveform},
+};
+
+const EFFECT_VOLUME: f32 = 0.2;
+const SEMITONE: f32 = 1.059_463;
+const EFFECT_ENVELOPE: Envelope = Envelope {
+    attack: 0.002,
+    decay: 0.02,
+    sustain: 0.7,
+    release: 0.01,
+};
+
+pub type EffectSource = Box<dyn Source<Item = f32> + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SoundEffect {
+    Move,
+    Rotate,
+    HardDrop,
+    Lock,
+    LineClear(usize),
+    TSpin(usize),
+    LevelUp(usize),
+    Danger,
+    GameOver,
+}
+
+pub fn get_sound_effect(event: &GameEvent) -> Option<SoundEffect> {
+    match event {
+        GameEvent::Moved => Some(SoundEffect::Move),
+        GameEvent::Rotated => Some(SoundEffect::Rotate),
+        GameEvent::HardDropped => Some(SoundEffect::HardDrop),
+        GameEvent::PieceLocked(_) => Some(SoundEffect::Lock),
+        GameEvent::LinesCleared(rows) => Some(SoundEffect::LineClear(rows.len())),
+        GameEvent::TSpin(lines) => Some(SoundEffect::TSpin(*lines)),
+        GameEvent::LevelUp(level) => Some(SoundEffect::LevelUp(*level)),
+        GameEvent::Danger(true) => Some(SoundEffect::Danger),
+        GameEvent::Danger(false) => None,
+        GameEvent::ToppedOut => Some(SoundEffect::GameOver),
+    }
+}
+
+// Every effect is a short run of chip tones, given as frequency in Hz and length in ms
+pub fn create_effect_source(effect: SoundEffect) -> EffectSource {
+    match effect {
+        SoundEffect::Move => create_tones(Waveform::Square, &[(660.0, 25)]),
+        SoundEffect::Rotate => create_tones(Waveform::Triangle, &[(880.0, 40)]),
+        SoundEffect::HardDrop => create_tones(Waveform::Noise, &[(1.0, 80)]),
+        SoundEffect::Lock => create_tones(Waveform::Noise, &[(1.0, 30)]),
+        // More lines climb higher
+        SoundEffect::LineClear(1) => create_tones(Waveform::Square, &[(523.0, 60), (659.0, 90)]),
+        SoundEffect::LineClear(2) => {
+            create_tones(Waveform::Square, &[(523.0, 60), (659.0, 60), (784.0, 90)])
+        }
+        SoundEffect::LineClear(3) => create_tones(
+            Waveform::Square,
+            &[(523.0, 50), (659.0, 50), (784.0, 50), (1047.0, 100)],
+        ),
+        SoundEffect::LineClear(_) => create_tones(
+            Waveform::Square,
+            &[
+                (523.0, 50),
+                (659.0, 50),
+                (784.0, 50),
+                (1047.0, 50),
+                (1319.0, 150),
+            ],
+        ),
+        SoundEffect::TSpin(0) => create_tones(Waveform::Triangle, &[(740.0, 40), (988.0, 60)]),
+        SoundEffect::TSpin(_) => create_tones(
+            Waveform::Triangle,
+            &[(740.0, 40), (988.0, 40), (740.0, 40), (988.0, 80)],
+        ),
+        // Each level starts the fanfare a semitone higher
+        SoundEffect::LevelUp(level) => {
+            let base = 392.0 * SEMITONE.powi(level as i32 - 2);
+            create_tones(
+                Waveform::Square,
+                &[(base, 80), (base * 4.0 / 3.0, 80), (base * 2.0, 160)],
+            )
+        }
+        SoundEffect::Danger => create_tones(
+            Waveform::Square,
+            &[(880.0, 90), (660.0, 90), (880.0, 90), (660.0, 90)],
+        ),
+        SoundEffect::GameOver => create_tones(
+            Waveform::Triangle,
+            &[(392.0, 150), (330.0, 150), (262.0, 150), (196.0, 400)],
+        ),
+    }
+}
+
+fn create_tones(waveform: Waveform, tones: &[(f32, u64)]) -> EffectSource {
+    let notes = tones
+        .iter()
+        .map(|&(frequency, milliseconds)| Note::new(frequency, milliseconds as f32 / 1000.0))
+        .collect();
+    Box::new(Voice::new(
+        waveform,
+        EFFECT_ENVELOPE,
+        EFFECT_VOLUME,
+        notes,
+        false,
+    ))
+}
@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const CLEAR_TYPE_NAMES: [&str; 4] = ["Singles", "Doubles", "Triples", "Tetrises"];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GameStats {
+    pub points: usize,
+    pub lines: usize,
+    pub level: usize,
+    pub pieces: usize,
+    pub elapsed: Duration,
+    pub max_combo: usize,
+    pub clear_types: [usize; 4],
+    // Time at every split, for modes that take them
+    pub splits: Vec<Duration>,
+    pub t_spins: usize,
+    combo_streak: usize,
+}
+
+impl GameStats {
+    pub fn new(level: usize) -> GameStats {
+        GameStats {
+            level,
+            ..Default::default()
+        }
+    }
+
+    pub fn record_lock(&mut self, lines_cleared: usize) {
+        self.pieces += 1;
+        if lines_cleared == 0 {
+            self.combo_streak = 0;
+            return;
+        }
+        self.lines += lines_cleared;
+        self.clear_types[lines_cleared.min(4) - 1] += 1;
+        self.combo_streak += 1;
+        // The first clear of a streak starts the combo, every following clear extends it
+        self.max_combo = self.max_combo.max(self.combo_streak - 1);
+    }
+
+    // Clears in a row after the first, zero when the last lock cleared nothing
+    pub fn get_combo(&self) -> usize {
+        self.combo_streak.saturating_sub(1)
+    }
+
+    // A clear can pass more than one split, they all get the same time
+    pub fn record_splits(&mut self, split_lines: usize) {
+        while self.splits.len() < self.lines / split_lines {
+            self.splits.push(self.elapsed);
+        }
+    }
+
+    pub fn get_pieces_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
+        }
+        self.pieces as f64 / seconds
+    }
+
+    pub fn get_points_per_minute(&self) -> f64 {
+        let minutes = self.elapsed.as_secs_f64() / 60.0;
+        if minutes == 0.0 {
+            return 0.0;
+        }
+        self.points as f64 / minutes
+    }
+}
+
+// For timed modes, where every millisecond counts
+pub fn format_precise_duration(duration: &Duration) -> String {
+    let milliseconds = duration.as_millis();
+    format!(
+        "{:02}:{:02}.{:03}",
+        milliseconds / 60_000,
+        milliseconds / 1000 % 60,
+        milliseconds % 1000
+    )
+}
+
+pub fn format_duration(duration: &Duration) -> String {
+    let centiseconds = duration.as_millis() / 10;
+    format!(
+        "{:02}:{:02}.{:02}",
+        centiseconds / 6000,
+        centiseconds / 100 % 60,
+        centiseconds % 100
+    )
+}
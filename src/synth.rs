@@ -0,0 +1,208 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::Source;
+
+pub const SAMPLE_RATE: u32 = 44_100;
+const NOISE_SEED: u32 = 0x1234_5678;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Noise,
+}
+
+// Attack, decay and release are in seconds, sustain is a level from 0 to 1
+#[derive(Debug, Clone, Copy)]
+pub struct Envelope {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Envelope {
+    // The release happens at the end of the note, so notes never overlap
+    fn get_level(&self, time: f32, note_length: f32) -> f32 {
+        let release_start = (note_length - self.release).max(0.0);
+        let level = if time < self.attack {
+            time / self.attack
+        } else if time < self.attack + self.decay {
+            1.0 - (1.0 - self.sustain) * (time - self.attack) / self.decay
+        } else {
+            self.sustain
+        };
+        if time < release_start {
+            level
+        } else {
+            level * (1.0 - (time - release_start) / self.release).max(0.0)
+        }
+    }
+}
+
+// A note without a frequency is a rest
+#[derive(Debug, Clone, Copy)]
+pub struct Note {
+    pub frequency: Option<f32>,
+    pub length: f32,
+}
+
+impl Note {
+    pub fn new(frequency: f32, length: f32) -> Note {
+        Note {
+            frequency: Some(frequency),
+            length,
+        }
+    }
+}
+
+// Shared with playing voices, so the tempo can change without restarting them.
+// Unlike changing the playback speed this keeps the pitch
+#[derive(Debug, Clone)]
+pub struct Tempo(Arc<AtomicU32>);
+
+impl Tempo {
+    pub fn new(tempo: f32) -> Tempo {
+        Tempo(Arc::new(AtomicU32::new(tempo.to_bits())))
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, tempo: f32) {
+        self.0.store(tempo.to_bits(), Ordering::Relaxed);
+    }
+}
+
+// Plays a sequence of notes with one waveform, like a single channel of an old sound chip
+#[derive(Debug, Clone)]
+pub struct Voice {
+    waveform: Waveform,
+    envelope: Envelope,
+    volume: f32,
+    notes: Vec<Note>,
+    looping: bool,
+    note_index: usize,
+    note_sample: u32,
+    phase: f32,
+    noise_state: u32,
+    tempo: Option<Tempo>,
+}
+
+impl Voice {
+    pub fn new(
+        waveform: Waveform,
+        envelope: Envelope,
+        volume: f32,
+        notes: Vec<Note>,
+        looping: bool,
+    ) -> Voice {
+        Voice {
+            waveform,
+            envelope,
+            volume,
+            notes,
+            looping,
+            note_index: 0,
+            note_sample: 0,
+            phase: 0.0,
+            noise_state: NOISE_SEED,
+            tempo: None,
+        }
+    }
+
+    pub fn with_tempo(mut self, tempo: Tempo) -> Voice {
+        self.tempo = Some(tempo);
+        self
+    }
+
+    fn get_note_length(&self, note: &Note) -> f32 {
+        match &self.tempo {
+            Some(tempo) => note.length / tempo.get(),
+            None => note.length,
+        }
+    }
+
+    fn get_wave_sample(&mut self, frequency: f32) -> f32 {
+        let sample = match self.waveform {
+            Waveform::Square => {
+                if self.phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 4.0 * (self.phase - 0.5).abs() - 1.0,
+            Waveform::Noise => {
+                // Xorshift, the frequency has no effect on noise
+                self.noise_state ^= self.noise_state << 13;
+                self.noise_state ^= self.noise_state >> 17;
+                self.noise_state ^= self.noise_state << 5;
+                self.noise_state as f32 / u32::MAX as f32 * 2.0 - 1.0
+            }
+        };
+        self.phase = (self.phase + frequency / SAMPLE_RATE as f32).fract();
+        sample
+    }
+}
+
+impl Iterator for Voice {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.note_index >= self.notes.len() {
+            if !self.looping || self.notes.is_empty() {
+                return None;
+            }
+            self.note_index = 0;
+        }
+        let note = self.notes[self.note_index];
+        let note_length = self.get_note_length(&note);
+        let time = self.note_sample as f32 / SAMPLE_RATE as f32;
+        let sample = match note.frequency {
+            Some(frequency) => {
+                self.get_wave_sample(frequency)
+                    * self.envelope.get_level(time, note_length)
+                    * self.volume
+            }
+            None => 0.0,
+        };
+
+        self.note_sample += 1;
+        if self.note_sample as f32 >= note_length * SAMPLE_RATE as f32 {
+            self.note_index += 1;
+            self.note_sample = 0;
+            self.phase = 0.0;
+        }
+        Some(sample)
+    }
+}
+
+impl Source for Voice {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        if self.looping {
+            return None;
+        }
+        let seconds: f32 = self
+            .notes
+            .iter()
+            .map(|note| self.get_note_length(note))
+            .sum();
+        Some(Duration::from_secs_f32(seconds))
+    }
+}
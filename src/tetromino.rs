@@ -1,4 +1,4 @@
-use std::time::{SystemTime, SystemTimeError, UNIX_EPOCH};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub const BLOCK: char = '#';
 pub const EMPTY: char = ' ';
@@ -52,34 +52,60 @@ pub const SQUARE: [[char; TETROMINO_SIZE]; TETROMINO_SIZE] = [
     [EMPTY, EMPTY, EMPTY, EMPTY],
 ];
 
-pub fn get_random_tetromino() -> [[char; TETROMINO_SIZE]; TETROMINO_SIZE] {
-    let tetrominos = [SBAR1, SBAR2, LBAR1, LBAR2, TBAR, LINE, SQUARE];
-    let result = generate_random_number();
-    match result {
-        Ok(random_index) => {
-            return tetrominos[random_index];
-        }
-        Err(error) => {
-            eprint!("Failed to generate random number: {}\r\n", error);
-            std::process::exit(1)
-        }
-    }
+pub const TETROMINOS: [[[char; TETROMINO_SIZE]; TETROMINO_SIZE]; 7] =
+    [SBAR1, SBAR2, LBAR1, LBAR2, TBAR, LINE, SQUARE];
+
+// xorshift64*, the same seed always deals the same tetrominos
+#[derive(Debug, Clone)]
+pub struct Randomizer {
+    state: u64,
 }
 
-fn generate_random_number() -> Result<usize, SystemTimeError> {
-    let system_time = SystemTime::now().duration_since(UNIX_EPOCH);
-    match system_time {
-        Ok(time) => {
-            let pointer = &time.as_secs();
-            let random_value = pointer + std::process::id() as u64 % 7;
-            return Ok((random_value % 7) as usize);
+impl Randomizer {
+    pub fn new(seed: u64) -> Randomizer {
+        // Spread the seed so that small seeds do not start with similar states
+        let mut state = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        state ^= state >> 31;
+        if state == 0 {
+            state = 1;
         }
-        Err(error) => {
-            return Err(error);
+        Randomizer { state }
+    }
+
+    pub fn from_state(state: u64) -> Option<Randomizer> {
+        // xorshift never leaves the zero state
+        if state == 0 {
+            return None;
         }
+        Some(Randomizer { state })
+    }
+
+    pub fn get_state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_tetromino(&mut self) -> [[char; TETROMINO_SIZE]; TETROMINO_SIZE] {
+        TETROMINOS[(self.next_value() % TETROMINOS.len() as u64) as usize]
+    }
+
+    fn next_value(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
     }
 }
 
+pub fn generate_seed() -> u64 {
+    let time = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(time) => time.as_nanos() as u64,
+        Err(_) => 0,
+    };
+    time ^ ((std::process::id() as u64) << 32)
+}
+
 pub fn rotate_tetromino(tetromino: &mut [[char; 4]; 4]) {
     let tetromino_copy = tetromino.clone();
     let mut y = 0;
@@ -94,6 +120,15 @@ pub fn rotate_tetromino(tetromino: &mut [[char; 4]; 4]) {
     }
 }
 
+pub fn rotate_tetromino_clockwise(tetromino: &mut [[char; 4]; 4]) {
+    let tetromino_copy = *tetromino;
+    for (y, row) in tetromino.iter_mut().enumerate() {
+        for (x, cell) in row.iter_mut().enumerate() {
+            *cell = tetromino_copy[TETROMINO_SIZE - 1 - x][y];
+        }
+    }
+}
+
 pub fn is_top_of_tetromino_empty(tetromino: &[[char; 4]; 4]) -> bool {
     let mut empty_counter = 0;
     for j in 0..TETROMINO_SIZE {
@@ -0,0 +1,124 @@
+use rodio::Source;
+
+use crate::synth::{Envelope, Note, Tempo, Voice, Waveform};
+
+const BEAT_SECONDS: f32 = 0.4;
+const REST: u8 = 0;
+// MIDI note number of A4, tuned to 440 Hz
+const MIDI_A4: f32 = 69.0;
+
+const LEAD_ENVELOPE: Envelope = Envelope {
+    attack: 0.005,
+    decay: 0.08,
+    sustain: 0.6,
+    release: 0.03,
+};
+const BASS_ENVELOPE: Envelope = Envelope {
+    attack: 0.005,
+    decay: 0.05,
+    sustain: 0.8,
+    release: 0.02,
+};
+const DRUM_ENVELOPE: Envelope = Envelope {
+    attack: 0.001,
+    decay: 0.04,
+    sustain: 0.0,
+    release: 0.01,
+};
+
+// Korobeiniki, as MIDI notes and lengths in beats
+const MELODY: [(u8, f32); 40] = [
+    (76, 1.0),
+    (71, 0.5),
+    (72, 0.5),
+    (74, 1.0),
+    (72, 0.5),
+    (71, 0.5),
+    (69, 1.0),
+    (69, 0.5),
+    (72, 0.5),
+    (76, 1.0),
+    (74, 0.5),
+    (72, 0.5),
+    (71, 1.5),
+    (72, 0.5),
+    (74, 1.0),
+    (76, 1.0),
+    (72, 1.0),
+    (69, 1.0),
+    (69, 1.0),
+    (REST, 1.0),
+    (REST, 0.5),
+    (74, 1.0),
+    (77, 0.5),
+    (81, 1.0),
+    (79, 0.5),
+    (77, 0.5),
+    (76, 1.5),
+    (72, 0.5),
+    (76, 1.0),
+    (74, 0.5),
+    (72, 0.5),
+    (71, 1.0),
+    (71, 0.5),
+    (72, 0.5),
+    (74, 1.0),
+    (76, 1.0),
+    (72, 1.0),
+    (69, 1.0),
+    (69, 1.0),
+    (REST, 1.0),
+];
+// One root note per bar, played as alternating octaves
+const BASS_ROOTS: [u8; 8] = [40, 45, 44, 45, 38, 36, 44, 45];
+const BEATS_PER_BAR: usize = 4;
+
+// The melody, bass and drums all loop over the same eight bars and share the tempo,
+// so they stay in sync
+pub fn create_theme_source(tempo: &Tempo) -> impl Source<Item = f32> + Send {
+    let melody = MELODY
+        .iter()
+        .map(|&(midi_note, beats)| create_note(midi_note, beats))
+        .collect();
+    let bass = BASS_ROOTS
+        .iter()
+        .flat_map(|&root| {
+            (0..BEATS_PER_BAR * 2).map(move |eighth| {
+                let midi_note = if eighth % 2 == 0 { root } else { root + 12 };
+                create_note(midi_note, 0.5)
+            })
+        })
+        .collect();
+    let drums = (0..BASS_ROOTS.len() * BEATS_PER_BAR * 2)
+        .map(|eighth| {
+            if eighth % 2 == 0 {
+                // Noise ignores the frequency, any will do
+                Note::new(1.0, 0.5 * BEAT_SECONDS)
+            } else {
+                create_note(REST, 0.5)
+            }
+        })
+        .collect();
+
+    let lead = Voice::new(Waveform::Square, LEAD_ENVELOPE, 0.12, melody, true);
+    let bass = Voice::new(Waveform::Triangle, BASS_ENVELOPE, 0.3, bass, true);
+    let drums = Voice::new(Waveform::Noise, DRUM_ENVELOPE, 0.08, drums, true);
+    lead.with_tempo(tempo.clone())
+        .mix(bass.with_tempo(tempo.clone()))
+        .mix(drums.with_tempo(tempo.clone()))
+}
+
+fn create_note(midi_note: u8, beats: f32) -> Note {
+    if midi_note == REST {
+        Note {
+            frequency: None,
+            length: beats * BEAT_SECONDS,
+        }
+    } else {
+        Note::new(get_midi_frequency(midi_note), beats * BEAT_SECONDS)
+    }
+}
+
+fn get_midi_frequency(midi_note: u8) -> f32 {
+    440.0 * 2.0_f32.powf((midi_note as f32 - MIDI_A4) / 12.0)
+}